@@ -1,14 +1,23 @@
 use word_comparison::word_file::{WordsInFile, WORD_MMAP_FILE};
 use word_comparison::listed_keywords::ListedKeywords;
-use word_comparison::sentences::TokenizedSentence;
+use word_comparison::spelling::SpellingCorrector;
+use word_comparison::tokenizer::{StandardTokenizer, SpellingCorrection, Tokenizer};
 
 fn main() -> std::io::Result<()>{
     let words = WordsInFile::read_word_file(WORD_MMAP_FILE)?;
-    let keywords = ListedKeywords::load(ListedKeywords::STD_LOCATION)?;
+    let keywords = ListedKeywords::load_with_normalization(ListedKeywords::STD_LOCATION,words.normalization())?;
     let sentence = "Was it 5G interference that caused my phone data to stop working after I had my second covid vaccine? Or was it ScoMo's cat's left ear?";
+    let tokenizer = StandardTokenizer::new(&words,&keywords);
     println!("Parsing {}",sentence);
-    let parsed = TokenizedSentence::tokenize(sentence, &words, &keywords);
-    parsed.explain(&words,&keywords);
+    let parsed = tokenizer.tokenize(sentence);
+    parsed.explain(&tokenizer);
+
+    let sentence = "Is the vacinne approved by Morisson?";
+    println!("Parsing {} with spelling correction",sentence);
+    let corrector = SpellingCorrector::new(&words,&keywords,Default::default());
+    let tokenizer = SpellingCorrection::new(tokenizer,&corrector);
+    let parsed = tokenizer.tokenize(sentence);
+    parsed.explain(&tokenizer);
 
     println!("wood {:?}",words.index_starting("wood"));
     println!("wood? {:?}",words.index_starting("wood?"));
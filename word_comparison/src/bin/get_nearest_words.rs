@@ -1,12 +1,135 @@
 //! Get words closest to other words.
-//! Use to create (once) a synonym list.
+//! Use to create (once) a synonym list, and to add words to it later.
 
-use word_comparison::word::{read_glove, WordSource};
+use word_comparison::word::{read_glove, read_word_counts, WordSource, MemoryWords, WordVecs};
+use word_comparison::embedding_reader::{read_embedding_file, EmbeddingFormat, EmbeddingOptions};
 use word_comparison::near_words::{WordAndValue, SmallestN, print_near_words_vec};
-use word_comparison::word_file::{write_word_file, WordsInFile, WORD_MMAP_FILE};
+use word_comparison::word_file::{write_word_file_with_options, WordsInFile, WORD_MMAP_FILE, WordFileOptions, WordFileContents, VectorQuantization, tag_name};
+use word_comparison::vocabulary_extension::{extend_vocabulary, new_words_from_vectors, read_listed_new_words, NewWordSynonyms};
+use word_comparison::synonym_search::{SynonymSearch, LshParameters};
+use word_comparison::synonym_overlay::SynonymOverlay;
+use word_comparison::lemmas::Lemmas;
+use word_comparison::normalize::Normalization;
+use word_comparison::phrases::{add_phrases, detect_collocations, read_phrase_list, CollocationOptions};
 
 
-fn bad_args() { println!("Arguments should be `create <source_path>' or `test' (or old)");}
+fn bad_args() {
+    println!("Arguments should be one of");
+    println!("  create <source_path> [--approximate] [--recall <sample size>] [--threads <n>] [--synonyms <n>] [--min-cosine <x>] [--counts <word counts path>] [--overlay <synonym overlay path>] [--lemmas <lemmas path>] [--phrases <phrase list path>] [--collocations <corpus path>] [--vectors f16|int8] [--format glove|word2vec|fasttext|word2vec-bin] [--cased] [--strip-accents]");
+    println!("  extend [--vectors <new word vectors path> [--existing-vectors <source_path used for create>]] [--listed <new words csv path>] [--synonyms <n>] [--min-cosine <x>] [--format glove|word2vec|fasttext|word2vec-bin] [--cased]");
+    println!("  lookup <word>");
+    println!("  prefix <text>");
+    println!("  stats");
+    println!("  dump --tsv");
+    println!("  diff <a.bin> <b.bin>");
+    println!("  test");
+    println!("  old");
+}
+
+/// Remove the options about how to read word vectors from args, returning them and the remaining arguments, or None if they don't make sense.
+fn take_embedding_options(args:&[String]) -> Option<(Option<EmbeddingFormat>,EmbeddingOptions,Vec<String>)> {
+    let mut format = None;
+    let mut options = EmbeddingOptions{ normalization: Some(Normalization::default()), ..Default::default() };
+    let mut remaining = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = Some(match args.next()?.as_str() {
+                    "glove" => EmbeddingFormat::Glove,
+                    "word2vec" | "fasttext" => EmbeddingFormat::Word2VecText,
+                    "word2vec-bin" => EmbeddingFormat::Word2VecBinary,
+                    _ => return None,
+                });
+            }
+            "--cased" => { options.cased=true; }
+            "--strip-accents" => { options.normalization=Some(Normalization{ strip_accents: true }); }
+            _ => remaining.push(arg.clone()),
+        }
+    }
+    Some((format,options,remaining))
+}
+
+/// Remove the options giving phrases from args, returning the phrases and the remaining arguments, or None if they don't make sense.
+fn take_phrase_options(args:&[String]) -> Option<(Vec<String>,Vec<String>)> {
+    let mut phrases = vec![];
+    let mut remaining = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--phrases" => {
+                let path = args.next()?;
+                match read_phrase_list(path) {
+                    Ok(list) => phrases.extend(list),
+                    Err(e) => { println!("Could not read phrases {} : {}",path,e); return None; }
+                }
+            }
+            "--collocations" => {
+                let path = args.next()?;
+                match detect_collocations(path,&CollocationOptions::default()) {
+                    Ok(found) => {
+                        println!("Found {} collocations in {}",found.len(),path);
+                        phrases.extend(found.into_iter().map(|(phrase,_)|phrase));
+                    }
+                    Err(e) => { println!("Could not read corpus {} : {}",path,e); return None; }
+                }
+            }
+            _ => remaining.push(arg.clone()),
+        }
+    }
+    Some((phrases,remaining))
+}
+
+/// Read word vectors, guessing the format if not given.
+fn read_vectors(path:&str,format:Option<EmbeddingFormat>,options:&EmbeddingOptions) -> std::io::Result<(MemoryWords,WordVecs)> {
+    let format = match format { Some(format) => format, None => EmbeddingFormat::guess(path)? };
+    read_embedding_file(path,format,options)
+}
+
+/// Parse the options after `create <source_path>`, or None if they don't make sense.
+fn parse_create_options(args:&[String],words:&MemoryWords,normalization:Normalization) -> Option<WordFileOptions> {
+    let mut options = WordFileOptions{ normalization, ..Default::default() };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--approximate" => { options.search = SynonymSearch::Approximate(LshParameters::suggested(words.len())); }
+            "--recall" => { options.recall_sample_size = args.next()?.parse().ok()?; }
+            "--threads" => { options.threads = Some(args.next()?.parse().ok()?); }
+            "--synonyms" => { options.num_synonyms = args.next()?.parse().ok()?; }
+            "--min-cosine" => { options.min_cosine = Some(args.next()?.parse().ok()?); }
+            "--vectors" => {
+                options.vectors = Some(match args.next()?.as_str() {
+                    "f16" => VectorQuantization::F16,
+                    "int8" => VectorQuantization::Int8,
+                    _ => return None,
+                });
+            }
+            "--counts" => {
+                let path = args.next()?;
+                match read_word_counts(path,words,options.normalization) {
+                    Ok(counts) => options.word_counts = Some(counts),
+                    Err(e) => { println!("Could not read word counts {} : {}",path,e); return None; }
+                }
+            }
+            "--lemmas" => {
+                let path = args.next()?;
+                match Lemmas::load(path) {
+                    Ok(lemmas) => options.lemmas = Some(lemmas),
+                    Err(e) => { println!("Could not read lemmas {} : {}",path,e); return None; }
+                }
+            }
+            "--overlay" => {
+                let path = args.next()?;
+                match SynonymOverlay::load(path) {
+                    Ok(overlay) => options.synonym_overlay = Some(overlay),
+                    Err(e) => { println!("Could not read synonym overlay {} : {}",path,e); return None; }
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(options)
+}
 
 fn main() -> std::io::Result<()>{
     let args: Vec<String> = std::env::args().collect();
@@ -14,9 +137,31 @@ fn main() -> std::io::Result<()>{
         match args[1].as_str() {
             "create" => {
                 let path = if args.len()>2 { args[2].as_str() } else { bad_args(); return Ok(())};
-                let (words,wordvecs) = read_glove(path,None)?;
-                write_word_file(WORD_MMAP_FILE,&words,&wordvecs,20)?;
+                let (format,embedding_options,args) = if let Some(res) = take_embedding_options(&args[3..]) { res } else { bad_args(); return Ok(())};
+                let (mut words,mut wordvecs) = read_vectors(path,format,&embedding_options)?;
+                let (phrases,args) = if let Some(res) = take_phrase_options(&args) { res } else { bad_args(); return Ok(())};
+                if !phrases.is_empty() {
+                    let report = add_phrases(&mut words,&mut wordvecs,&phrases,embedding_options.normalization.unwrap_or_default());
+                    println!("Added {} phrases",report.added);
+                    for phrase in report.unknown_components { println!("Phrase {} has a word not in the vocabulary",phrase); }
+                }
+                let options = if let Some(options) = parse_create_options(&args,&words,embedding_options.normalization.unwrap_or_default()) { options } else { bad_args(); return Ok(())};
+                let report = write_word_file_with_options(WORD_MMAP_FILE,&words,&wordvecs,&options)?;
+                println!("Found synonyms for {} words in {:.1} seconds",words.len(),report.synonym_search_time.as_secs_f64());
+                if let Some(recall) = report.recall {
+                    println!("Recall compared to exact search on {} words : {:.3}",recall.sample_size,recall.recall);
+                }
+                for word in report.unknown_overlay_words {
+                    println!("Synonym overlay word {} is not in the vocabulary",word);
+                }
+                if report.ignored_lemmas>0 { println!("Ignored {} lemma pairs not in the vocabulary",report.ignored_lemmas); }
             }
+            "extend" => { if extend_word_file(&args[2..]).is_none() { bad_args(); } }
+            "lookup" if args.len()==3 => { lookup_word(&args[2])?; }
+            "prefix" if args.len()==3 => { show_prefix(&args[2])?; }
+            "stats" => { print_stats()?; }
+            "dump" if args.len()==3 && args[2]=="--tsv" => { dump_tsv()?; }
+            "diff" if args.len()==4 => { diff_word_files(&args[2],&args[3])?; }
             "test" => { check_word_file()?; }
             "old" => { print_text()?; }
             _ => bad_args()
@@ -25,6 +170,152 @@ fn main() -> std::io::Result<()>{
     Ok(())
 }
 
+/// Add new words to the end of the word file, or None if the arguments are wrong.
+fn extend_word_file(args:&[String]) -> Option<()> {
+    let (format,embedding_options,args) = take_embedding_options(args)?;
+    let mut new_words = vec![];
+    let mut existing_vectors = None;
+    let mut num_synonyms = 20;
+    let mut min_cosine = None;
+    let mut args = args.iter();
+    let report_error = |what:&str,path:&str,e:std::io::Error| println!("Could not read {} {} : {}",what,path,e);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--vectors" => {
+                let path = args.next()?;
+                match read_vectors(path,format,&embedding_options) {
+                    Ok((words,wordvecs)) => new_words.extend(new_words_from_vectors(&words,&wordvecs)),
+                    Err(e) => { report_error("new word vectors",path,e); return Some(()); }
+                }
+            }
+            "--existing-vectors" => {
+                let path = args.next()?;
+                match read_vectors(path,format,&embedding_options) {
+                    Ok(existing) => existing_vectors = Some(existing),
+                    Err(e) => { report_error("existing vectors",path,e); return Some(()); }
+                }
+            }
+            "--listed" => {
+                let path = args.next()?;
+                match read_listed_new_words(path) {
+                    Ok(listed) => new_words.extend(listed),
+                    Err(e) => { report_error("new words",path,e); return Some(()); }
+                }
+            }
+            "--synonyms" => { num_synonyms = args.next()?.parse().ok()?; }
+            "--min-cosine" => { min_cosine = Some(args.next()?.parse().ok()?); }
+            _ => return None,
+        }
+    }
+    let result : std::io::Result<()> = (||{
+        let mut contents = WordFileContents::from_word_file(&WordsInFile::read_word_file(WORD_MMAP_FILE)?);
+        if new_words.iter().any(|w|matches!(w.synonyms,NewWordSynonyms::Vector(_))) && existing_vectors.is_none() && contents.vectors.is_none() {
+            println!("{} has no vectors stored in it, so --existing-vectors is needed to find synonyms for new words with vectors",WORD_MMAP_FILE);
+            return Ok(());
+        }
+        let report = extend_vocabulary(&mut contents,existing_vectors.as_ref().map(|(words,wordvecs)|(words,wordvecs)),new_words,num_synonyms,min_cosine);
+        println!("Added {} words",report.added);
+        for word in report.already_present { println!("{} is already in the vocabulary",word); }
+        for word in report.unknown_synonyms { println!("Listed synonym {} is not in the vocabulary",word); }
+        // write to a new file and rename it, as a running program may have the old one memory mapped.
+        let new_path = format!("{}.new",WORD_MMAP_FILE);
+        contents.write(&new_path)?;
+        std::fs::rename(&new_path,WORD_MMAP_FILE)
+    })();
+    if let Err(e) = result { println!("Could not extend {} : {}",WORD_MMAP_FILE,e); }
+    Some(())
+}
+
+/// Print everything known about a word.
+fn lookup_word(word:&str) -> std::io::Result<()> {
+    let words = WordsInFile::read_word_file(WORD_MMAP_FILE)?;
+    let word = words.normalization().normalize(word);
+    if let Some(index) = words.index(&word) {
+        println!("{} is word index {}, rank {} of {}",word,index.0,index.0+1,words.len());
+        if let Some(count) = words.frequency(index) { println!("Occurs {} times, idf {:.2}",count,words.idf(index).unwrap()); }
+        if words.lemma(index)!=index { println!("Lemma {}",words.word(words.lemma(index))); }
+        for synonym in words.synonyms(index) { println!("  {}\t{:.4}",words.word(synonym.word),synonym.value); }
+    } else {
+        println!("{} is not in the vocabulary",word);
+    }
+    Ok(())
+}
+
+/// Print the longest word at the start of the text, as used when tokenizing.
+fn show_prefix(text:&str) -> std::io::Result<()> {
+    let words = WordsInFile::read_word_file(WORD_MMAP_FILE)?;
+    let text = words.normalization().normalize(text);
+    match words.index_starting(&text) {
+        Some((index,used)) => println!("{} (word index {}) using {} bytes, leaving {:?}",words.word(index),index.0,used,&text[used..]),
+        None => println!("No word is a prefix of {:?}",text),
+    }
+    Ok(())
+}
+
+/// Print a summary of the word file.
+fn print_stats() -> std::io::Result<()> {
+    let words = WordsInFile::read_word_file(WORD_MMAP_FILE)?;
+    let synonym_counts : Vec<usize> = words.all_indices().map(|i|words.synonyms(i).len()).collect();
+    let total_synonyms : usize = synonym_counts.iter().sum();
+    println!("{} : format version {}, {} bytes",WORD_MMAP_FILE,words.format_version(),words.file_length());
+    println!("{} words",words.len());
+    println!("{} synonyms, mean {:.1} per word, maximum {}",total_synonyms,total_synonyms as f64/words.len().max(1) as f64,synonym_counts.iter().max().unwrap_or(&0));
+    if let Some(total) = words.total_frequency() { println!("Word counts total {}",total); }
+    if let Some(dimensions) = words.vector_dimensions() { println!("Vectors with {} dimensions stored as {:?}",dimensions,words.vector_quantization().unwrap()); }
+    println!("Words with a lemma other than themselves : {}",words.all_indices().filter(|&i|words.lemma(i)!=i).count());
+    for (tag,length) in words.sections() { println!("Section {} : {} bytes",tag_name(tag),length); }
+    Ok(())
+}
+
+/// Print the vocabulary as tab separated values, one word per line.
+fn dump_tsv() -> std::io::Result<()> {
+    use std::io::Write;
+    let words = WordsInFile::read_word_file(WORD_MMAP_FILE)?;
+    let stdout = std::io::stdout();
+    let mut out = std::io::BufWriter::new(stdout.lock());
+    writeln!(out,"index\tword\tcount\tlemma\tsynonyms")?;
+    for i in words.all_indices() {
+        let count = words.frequency(i).map(|c|c.to_string()).unwrap_or_default();
+        let synonyms : Vec<String> = words.synonyms(i).iter().map(|s|format!("{}:{:.4}",words.word(s.word),s.value)).collect();
+        writeln!(out,"{}\t{}\t{}\t{}\t{}",i.0,words.word(i),count,words.word(words.lemma(i)),synonyms.join(" "))?;
+    }
+    out.flush()
+}
+
+/// Compare the synonym lists of two word files, matching words by text as indices may differ.
+fn diff_word_files(path_a:&str,path_b:&str) -> std::io::Result<()> {
+    let a = WordsInFile::read_word_file(path_a)?;
+    let b = WordsInFile::read_word_file(path_b)?;
+    let synonym_text = |words:&WordsInFile,i| -> Vec<String> { words.synonyms(i).iter().map(|s|words.word(s.word).to_string()).collect() };
+    let mut changed = 0;
+    let mut overlap_total = 0.0;
+    let mut common = 0;
+    for i in a.all_indices() {
+        let word = a.word(i);
+        if let Some(j) = b.index(word) {
+            common+=1;
+            let synonyms_a = synonym_text(&a,i);
+            let synonyms_b = synonym_text(&b,j);
+            let removed : Vec<&String> = synonyms_a.iter().filter(|s|!synonyms_b.contains(s)).collect();
+            let added : Vec<&String> = synonyms_b.iter().filter(|s|!synonyms_a.contains(s)).collect();
+            let union = synonyms_a.len()+added.len();
+            overlap_total += if union==0 { 1.0 } else { (synonyms_a.len()-removed.len()) as f64/union as f64 };
+            if !removed.is_empty() || !added.is_empty() {
+                changed+=1;
+                let join = |v:&[&String]| v.iter().map(|s|s.as_str()).collect::<Vec<_>>().join(" ");
+                println!("{}\t-{}\t+{}",word,join(&removed),join(&added));
+            }
+        } else {
+            println!("{}\tonly in {}",word,path_a);
+        }
+    }
+    for j in b.all_indices() {
+        if a.index(b.word(j)).is_none() { println!("{}\tonly in {}",b.word(j),path_b); }
+    }
+    println!("{} words in {}, {} in {}, {} in both of which {} have different synonyms. Mean synonym overlap (Jaccard) {:.3}",a.len(),path_a,b.len(),path_b,common,changed,overlap_total/common.max(1) as f64);
+    Ok(())
+}
+
 fn check_word_file() -> std::io::Result<()>{
     let words = WordsInFile::read_word_file(WORD_MMAP_FILE)?;
     for i in words.all_indices() {
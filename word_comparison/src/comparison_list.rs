@@ -1,28 +1,54 @@
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
-use crate::listed_keywords::ListedKeywords;
-use crate::word::WordIndex;
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::word::{WordIndex, WordSource};
 use crate::word_file::WordsInFile;
 use crate::sentences::{TokenizedSentence, SentencePart};
+use crate::tokenizer::Tokenizer;
 use serde::{Serialize,Deserialize};
 use crate::database_backend::{InternalQuestionId, ParsedQuestion, WordComparisonDatabaseBackend};
+use crate::stopwords::StopwordPolicy;
+use crate::typed_tokens::TypedValue;
 
 
 /// Add a new question to the database.
-pub fn add_question<B:WordComparisonDatabaseBackend>(backend:&mut B,question:&str,external_id:B::ExternalQuestionId,words:&WordsInFile,keywords:&ListedKeywords) -> anyhow::Result<InternalQuestionId> {
+/// The tokenizer and stopwords should be the same as used for [find_similar_in_database], apart from query only stages
+/// such as [SpellingCorrection](crate::tokenizer::SpellingCorrection).
+pub fn add_question<B:WordComparisonDatabaseBackend,T:Tokenizer>(backend:&mut B,question:&str,external_id:B::ExternalQuestionId,tokenizer:&T,stopwords:&StopwordPolicy) -> anyhow::Result<InternalQuestionId> {
     let question = question.replace('\n'," ");
-    let parsed_question = ParsedQuestion::new(question, words, keywords);
+    let parsed_question = ParsedQuestion::new(question, tokenizer, stopwords);
     backend.add_sentence_and_components(external_id,parsed_question)
 }
 
 
 /// the score for a Keyword - one of the words from the ListedKeywords list.
 const SCORE_KEYWORD : f64 = 10.0;
-/// The score for a matching word that is not in either the ListedKeywords or general lexicon. Possibly a hashtag?
+/// The score for a matching word that is not in either the ListedKeywords or general lexicon, or a matching hashtag or mention.
 const SCORE_UNIQUE : f64 = 10.0;
+/// The score for a matching number, amount of money, date or the like. Plain numbers are common, and not worth much,
+/// while legislation references are as specific as a keyword.
+fn score_typed(value:&TypedValue) -> f64 {
+    match value {
+        TypedValue::Number(_) => 1.0,
+        TypedValue::Percentage(_) | TypedValue::Year(_) => 3.0,
+        TypedValue::YearRange(..) => 5.0,
+        TypedValue::Money{..} => 6.0,
+        TypedValue::Date{..} => 8.0,
+        TypedValue::Legislation(_) => SCORE_KEYWORD,
+    }
+}
+/// When the vocabulary has word counts, the score for a known word is its inverse document frequency minus this, clamped to [SCORE_KNOWN_MIN,SCORE_KNOWN_MAX].
+/// For a typical corpus, this gives about the same scores as the rank based buckets below : a rank 100 word has idf about 7, rank 100000 about 14.
+const IDF_OFFSET : f64 = 6.0;
+const SCORE_KNOWN_MIN : f64 = 1.0;
+const SCORE_KNOWN_MAX : f64 = 8.0;
+/// A word with the same lemma as a word in the question (e.g. "vaccinated" for "vaccinations") gets this fraction of the score of the word itself.
+const LEMMA_MATCH_FRACTION : f64 = 0.95;
 /// The score for a word in the general vocabulary. More obscure words are worth more points.
-fn score_known(word : WordIndex) -> f64 {
-    if word.0 < 100 { 1.0 }
+/// Uses the real word frequency if the vocabulary has it, otherwise guesses from the rank.
+fn score_known(word : WordIndex,words:&WordsInFile) -> f64 {
+    if let Some(idf) = words.idf(word) { (idf-IDF_OFFSET).clamp(SCORE_KNOWN_MIN,SCORE_KNOWN_MAX) }
+    else if word.0 < 100 { 1.0 }
     else if word.0 < 500 { 2.0 }
     else if word.0 < 1000 { 3.0 }
     else if word.0 < 10000 { 4.0 }
@@ -32,33 +58,125 @@ fn score_known(word : WordIndex) -> f64 {
 
 
 /// Find questions in the database that are similar to this one.
-pub fn find_similar_in_database<B:WordComparisonDatabaseBackend>(backend:&mut B, question:&str, words:&WordsInFile, keywords:&ListedKeywords) -> anyhow::Result<Vec<ScoredIDs<B::ExternalQuestionId>>> {
-    let tokenized = TokenizedSentence::tokenize(&question,words,keywords);
-    // println!();
-    // tokenized.explain(words,keywords);
+/// The tokenizer and stopwords should be the same as used when adding questions to the database.
+pub fn find_similar_in_database<B:WordComparisonDatabaseBackend,T:Tokenizer>(backend:&mut B, question:&str, tokenizer:&T, stopwords:&StopwordPolicy) -> anyhow::Result<Vec<ScoredIDs<B::ExternalQuestionId>>> {
+    let tokenized = tokenizer.tokenize(question);
+    let internal_ids = score_candidates(backend,&tokenized,tokenizer,stopwords)?.extract_ordered();
+    backend.convert_internal_ids_to_external_ids(internal_ids)
+}
+
+/// Like [find_similar_in_database], but also say which parts of the question matched which parts of each similar question,
+/// so they can be highlighted.
+pub fn find_similar_in_database_with_matches<B:WordComparisonDatabaseBackend,T:Tokenizer>(backend:&mut B, question:&str, tokenizer:&T, stopwords:&StopwordPolicy) -> anyhow::Result<Vec<ScoredIDsWithMatches<B::ExternalQuestionId>>> {
+    let tokenized = tokenizer.tokenize(question);
+    let internal_ids = score_candidates(backend,&tokenized,tokenizer,stopwords)?.extract_ordered();
+    let ids : Vec<InternalQuestionId> = internal_ids.iter().map(|scored|scored.id).collect();
+    let texts = backend.question_texts(&ids)?.into_iter().zip(&ids).map(|(text,id)|text.ok_or_else(||anyhow::anyhow!("No text for question {}",id.0))).collect::<anyhow::Result<Vec<String>>>()?;
+    let external_ids = backend.convert_internal_ids_to_external_ids(internal_ids)?;
+    Ok(external_ids.into_iter().zip(texts).map(|(scored,text)|{
+        let other = tokenizer.tokenize_stored(&text);
+        let mut matches = vec![];
+        for (query_part,query_span) in tokenized.parts.iter().zip(&tokenized.spans) {
+            for (question_part,question_span) in other.parts.iter().zip(&other.spans) {
+                if tokens_match(query_part,question_part,tokenizer,stopwords) { matches.push(MatchedSpans{ query: query_span.clone(), question: question_span.clone() }); }
+            }
+        }
+        // alternative tokens for the same text can both match, and not necessarily one after the other.
+        matches.sort_by_key(|m|(m.query.start,m.query.end,m.question.start,m.question.end));
+        matches.dedup();
+        ScoredIDsWithMatches{ id: scored.id, score: scored.score, question: text, matches }
+    }).collect())
+}
+
+/// Score all the questions in the database that share some token with the tokenized question.
+/// Where there are alternative tokens for the same text, a question gets the best score of them, not the sum.
+/// Matches via spelling corrections have their score multiplied by the correction's penalty.
+fn score_candidates<B:WordComparisonDatabaseBackend,T:Tokenizer>(backend:&B, tokenized:&TokenizedSentence, tokenizer:&T, stopwords:&StopwordPolicy) -> anyhow::Result<SentenceScores> {
     let mut scores = SentenceScores::default();
-    for token in &tokenized.parts {
-        match token {
-            SentencePart::Listed(word) => {
-                scores.add_maybe(backend.sentences_containing_listed_word(*word)?,SCORE_KEYWORD);
-            },
-            SentencePart::Known(word) => {
-                if word.0>100 {
-                    let score = score_known(*word);
-                    let mut avoid_twice = HashSet::new();
-                    scores.add_maybe_avoid_counting_twice(backend.sentences_containing_general_lexicon_word(*word)?,score,&mut avoid_twice);
-                    for e in words.synonyms(*word) {
-                        scores.add_maybe_avoid_counting_twice(backend.sentences_containing_general_lexicon_word(e.word)?,score*e.value as f64,&mut avoid_twice);
+    for alternatives in tokenized.alternatives() {
+        let mut span_scores = SpanScores::default();
+        for index in alternatives {
+            let part = &tokenized.parts[index];
+            let score = match part_score(part,tokenizer,stopwords) { Some(score) => score*tokenized.correction(index).map(|c|c.penalty).unwrap_or(1.0), None => continue };
+            match part {
+                SentencePart::Listed(word) => {
+                    span_scores.add_maybe(backend.sentences_containing_listed_word(*word)?,score);
+                },
+                SentencePart::Known(word) => {
+                    span_scores.add_maybe(backend.sentences_containing_general_lexicon_word(*word)?,score);
+                    for (related,fraction) in related_words(*word,tokenizer) {
+                        span_scores.add_maybe(match related {
+                            RelatedWord::Lemma(lemma) => backend.sentences_containing_lemma(lemma)?,
+                            RelatedWord::Synonym(synonym) => backend.sentences_containing_general_lexicon_word(synonym)?,
+                        },score*fraction);
                     }
                 }
+                SentencePart::Unknown(word) => {
+                    span_scores.add_maybe(backend.sentences_containing_unknown_word(word)?,score);
+                },
+                SentencePart::Hashtag(tag) => {
+                    span_scores.add_maybe(backend.sentences_containing_unknown_word(&format!("#{}",tag))?,score);
+                },
+                SentencePart::Mention(account) => {
+                    span_scores.add_maybe(backend.sentences_containing_unknown_word(&format!("@{}",account))?,score);
+                },
+                SentencePart::Typed(value) => {
+                    span_scores.add_maybe(backend.sentences_containing_typed_value(value)?,score);
+                },
             }
-            SentencePart::Unknown(word) => {
-                scores.add_maybe(backend.sentences_containing_unknown_word(word)?,SCORE_UNIQUE);
-            },
         }
+        scores.add_span(span_scores);
+    }
+    Ok(scores)
+}
+
+/// The points a question gets for containing the token itself, or None if it is a stopword and so not searched for.
+fn part_score<T:Tokenizer>(part:&SentencePart,tokenizer:&T,stopwords:&StopwordPolicy) -> Option<f64> {
+    match part {
+        SentencePart::Listed(_) => Some(SCORE_KEYWORD),
+        SentencePart::Known(word) => {
+            let (words,local) = tokenizer.vocabulary(*word);
+            if stopwords.is_stopword(local,words) { None } else { Some(score_known(local,words)) }
+        }
+        SentencePart::Unknown(_) | SentencePart::Hashtag(_) | SentencePart::Mention(_) => Some(SCORE_UNIQUE),
+        SentencePart::Typed(value) => Some(score_typed(value)),
+    }
+}
+
+/// Something a general vocabulary word in a query also matches, as an index in the tokenizer's vocabularies.
+enum RelatedWord {
+    /// Any word with this lemma.
+    Lemma(WordIndex),
+    Synonym(WordIndex),
+}
+
+/// The lemma (if the vocabulary has lemmas) and synonyms of a general vocabulary word, with the fraction of the word's score they get.
+fn related_words<T:Tokenizer>(word:WordIndex,tokenizer:&T) -> Vec<(RelatedWord,f64)> {
+    let (words,local) = tokenizer.vocabulary(word);
+    let global = |w:WordIndex| tokenizer.global_index(word,w);
+    let mut res = vec![];
+    if words.has_lemmas() { res.push((RelatedWord::Lemma(global(words.lemma(local))),LEMMA_MATCH_FRACTION)); }
+    for e in words.synonyms(local) { res.push((RelatedWord::Synonym(global(e.word)),e.value as f64)); }
+    res
+}
+
+/// Whether a token in a query would find a token in a question via the index, as in [score_candidates].
+fn tokens_match<T:Tokenizer>(query:&SentencePart,question:&SentencePart,tokenizer:&T,stopwords:&StopwordPolicy) -> bool {
+    match (query,question) {
+        (SentencePart::Listed(a),SentencePart::Listed(b)) => a==b,
+        (SentencePart::Known(a),SentencePart::Known(b)) => {
+            let ((words,a_local),(_,b_local)) = (tokenizer.vocabulary(*a),tokenizer.vocabulary(*b));
+            if tokenizer.global_index(*a,b_local)!=*b { return false; } // different vocabularies.
+            let (a,b) = (a_local,b_local);
+            !stopwords.is_stopword(a,words) && !stopwords.is_stopword(b,words) &&
+                (a==b || (words.has_lemmas() && words.lemma(a)==words.lemma(b)) || words.synonyms(a).iter().any(|e|e.word==b))
+        }
+        (SentencePart::Unknown(a),SentencePart::Unknown(b)) => a==b,
+        (SentencePart::Hashtag(a),SentencePart::Hashtag(b)) => a==b,
+        (SentencePart::Mention(a),SentencePart::Mention(b)) => a==b,
+        (SentencePart::Typed(a),SentencePart::Typed(b)) => a.key()==b.key(),
+        _ => false,
     }
-    let internal_ids = scores.extract_ordered();
-    backend.convert_internal_ids_to_external_ids(internal_ids)
 }
 
 
@@ -74,27 +192,135 @@ pub struct ScoredIDs<ID> {
     pub score : f64,
 }
 
+/// A part of the query that matched a part of a similar question.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct MatchedSpans {
+    /// The range of bytes in the query.
+    pub query : Range<usize>,
+    /// The range of bytes in the similar question.
+    pub question : Range<usize>,
+}
 
-impl SentenceScores {
-    /// Add a set of questions containing this id.
-    /// Assign the given number of points.
+/// Like [ScoredIDs], with the text of the similar question and what in it matched the query.
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct ScoredIDsWithMatches<ID> {
+    pub id : ID,
+    pub score : f64,
+    /// The text of the similar question, which [MatchedSpans::question] refers to.
+    pub question : String,
+    pub matches : Vec<MatchedSpans>,
+}
+
+/// What kind of token a [TokenExplanation] is.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum TokenKind {
+    Keyword,
+    Word,
+    Unknown,
+    Hashtag,
+    Mention,
+    /// A number, amount of money, date or the like.
+    Value,
+}
+
+/// How a query is tokenized and what is searched for, so it can be seen why questions are (or aren't) found. See [explain_query].
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct QueryExplanation {
+    pub question : String,
+    pub tokens : Vec<TokenExplanation>,
+}
+
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct TokenExplanation {
+    pub kind : TokenKind,
+    /// The canonical text : the keyword's first alias, the general vocabulary word, or the normalized text.
+    pub text : String,
+    /// The range of bytes in the query.
+    pub span : Range<usize>,
+    /// Whether this covers the same text as the previous token. Only the best scoring of such alternatives counts.
+    pub alternative : bool,
+    /// Whether the token is searched for. False for stopwords.
+    pub indexed : bool,
+    /// The points a question gets for containing the token, including any spelling correction penalty. 0 if not indexed.
+    pub score : f64,
+    /// If this is a spelling correction, the unknown word it corrects.
+    pub corrected_from : Option<String>,
+    /// For an indexed general vocabulary word, its lemma and synonyms, which are searched for too.
+    pub related : Vec<RelatedWordExplanation>,
+}
+
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct RelatedWordExplanation {
+    pub word : String,
+    /// Whether this is the lemma, matching any word with that lemma, rather than a synonym.
+    pub lemma : bool,
+    /// The fraction of the token's score that a question containing this gets.
+    pub weight : f64,
+}
+
+/// Explain how [find_similar_in_database] would tokenize and score a query, without searching the database.
+pub fn explain_query<T:Tokenizer>(question:&str,tokenizer:&T,stopwords:&StopwordPolicy) -> QueryExplanation {
+    let tokenized = tokenizer.tokenize(question);
+    let word_text = |word:WordIndex| { let (words,local) = tokenizer.vocabulary(word); words.word(local).to_string() };
+    let mut tokens = vec![];
+    for (index,(part,span)) in tokenized.parts.iter().zip(&tokenized.spans).enumerate() {
+        let (kind,text) = match part {
+            SentencePart::Listed(keyword) => (TokenKind::Keyword,tokenizer.keywords().canonical(*keyword).to_string()),
+            SentencePart::Known(word) => (TokenKind::Word,word_text(*word)),
+            SentencePart::Unknown(word) => (TokenKind::Unknown,word.clone()),
+            SentencePart::Hashtag(tag) => (TokenKind::Hashtag,format!("#{}",tag)),
+            SentencePart::Mention(account) => (TokenKind::Mention,format!("@{}",account)),
+            SentencePart::Typed(value) => (TokenKind::Value,value.to_string()),
+        };
+        let correction = tokenized.correction(index);
+        let score = part_score(part,tokenizer,stopwords);
+        let related = match (part,score) {
+            (SentencePart::Known(word),Some(_)) => related_words(*word,tokenizer).into_iter().map(|(related,weight)|match related {
+                RelatedWord::Lemma(lemma) => RelatedWordExplanation{ word: word_text(lemma), lemma: true, weight },
+                RelatedWord::Synonym(synonym) => RelatedWordExplanation{ word: word_text(synonym), lemma: false, weight },
+            }).collect(),
+            _ => vec![],
+        };
+        tokens.push(TokenExplanation{
+            kind, text,
+            span : span.clone(),
+            alternative : index>0 && tokenized.spans[index-1]==*span,
+            indexed : score.is_some(),
+            score : score.unwrap_or(0.0)*correction.map(|c|c.penalty).unwrap_or(1.0),
+            corrected_from : correction.map(|c|c.original.clone()),
+            related,
+        });
+    }
+    QueryExplanation{ question: question.to_string(), tokens }
+}
+
+
+/// The points each question gets for one span of text in the query.
+/// A question that matches the span in several ways (such as via a word and its synonym) gets the best score, not the sum.
+#[derive(Default)]
+struct SpanScores {
+    scores : HashMap<InternalQuestionId,f64>,
+}
+
+impl SpanScores {
+    /// Give the questions at least the given number of points.
+    #[allow(clippy::owned_cow)] // the type returned by WordComparisonDatabaseBackend.
     fn add_maybe(&mut self,qs:Option<Cow<Vec<InternalQuestionId>>>,points:f64) {
         if let Some(qs) = &qs {
             for &q in qs.as_ref() {
-                *self.scores.entry(q).or_insert(0.0)+=points;
+                let score = self.scores.entry(q).or_insert(0.0);
+                if *score<points { *score=points; }
             }
         }
     }
+}
 
-    /// like add_maybe, but
-    /// Don't assign points if it is in the avoid_twice optional list (and add ones that you do add points for).
-    fn add_maybe_avoid_counting_twice(&mut self,qs:Option<Cow<Vec<InternalQuestionId>>>,points:f64,avoid_twice:&mut HashSet<InternalQuestionId>) {
-        if let Some(qs) = qs {
-            for &q in qs.as_ref() {
-                if avoid_twice.insert(q) {
-                    *self.scores.entry(q).or_insert(0.0)+=points;
-                }
-            }
+impl SentenceScores {
+    /// Add the points for one span of the query.
+    fn add_span(&mut self,span:SpanScores) {
+        for (q,points) in span.scores {
+            *self.scores.entry(q).or_insert(0.0)+=points;
         }
     }
 
@@ -103,4 +329,125 @@ impl SentenceScores {
         res.sort_by(|a,b|b.score.partial_cmp(&a.score).unwrap());
         res
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spelling::SpellingCorrector;
+    use crate::tokenizer::{SpellingCorrection, StandardTokenizer};
+    use crate::flatfile_database_backend::FlatfileDatabaseBackend;
+    use crate::listed_keywords::ListedKeywords;
+    use crate::word_file::WordsInFile;
+    use crate::test_util::{keywords_from, small_word_file, temp_path};
+
+    /// The vocabulary from [small_word_file], the listed keyword "Covid" (also "Coronavirus"), and the two most common words as stopwords.
+    fn small_vocabulary(name:&str) -> (WordsInFile,ListedKeywords,StopwordPolicy) {
+        let words = WordsInFile::read_word_file(small_word_file(name)).unwrap();
+        (words,keywords_from(name,"Covid,Coronavirus\n"),StopwordPolicy::Rank(2))
+    }
+
+    /// A new question database with no questions in it.
+    fn empty_database<T:Tokenizer>(name:&str,tokenizer:&T,stopwords:&StopwordPolicy) -> FlatfileDatabaseBackend<u32> {
+        let path = temp_path(&format!("{}_database.txt",name));
+        let _ = std::fs::remove_file(&path);
+        FlatfileDatabaseBackend::new(&path,tokenizer,stopwords).unwrap()
+    }
+
+    #[test]
+    fn matched_spans() {
+        let (words,keywords,stopwords) = small_vocabulary("matched_spans");
+        let tokenizer = StandardTokenizer::new(&words,&keywords);
+        let mut backend = empty_database("matched_spans",&tokenizer,&stopwords);
+        add_question(&mut backend,"Is the kitten OK after Covid?",7,&tokenizer,&stopwords).unwrap();
+        let found = find_similar_in_database_with_matches(&mut backend,"My CAT,  my  Kitten, coronavirus",&tokenizer,&stopwords).unwrap();
+        assert_eq!(found.len(),1);
+        assert_eq!(found[0].id,7);
+        let matches : Vec<(Range<usize>,Range<usize>)> = found[0].matches.iter().map(|m|(m.query.clone(),m.question.clone())).collect();
+        assert_eq!(matches,vec![(3..6,7..13),(13..19,7..13),(21..32,23..28)]);
+    }
+
+    #[test]
+    fn keyword_that_is_also_a_word() {
+        let (words,_,stopwords) = small_vocabulary("keyword_word");
+        let keywords = keywords_from("keyword_word","Cat,Felix\n");
+        let tokenizer = StandardTokenizer::new(&words,&keywords);
+        let tokenized = tokenizer.tokenize("the cat");
+        assert!(matches!(tokenized.parts[1..],[SentencePart::Listed(_),SentencePart::Known(_)]));
+        assert_eq!(tokenized.alternatives(),vec![0..1,1..3]);
+        let mut backend = empty_database("keyword_word",&tokenizer,&stopwords);
+        add_question(&mut backend,"my cat",1,&tokenizer,&stopwords).unwrap();
+        add_question(&mut backend,"my kitten",2,&tokenizer,&stopwords).unwrap();
+        let found = find_similar_in_database(&mut backend,"Cat",&tokenizer,&stopwords).unwrap();
+        assert_eq!(found.len(),2); // the kitten is found via the synonyms of the word cat.
+        assert_eq!((found[0].id,found[0].score),(1,SCORE_KEYWORD)); // not also the score for the word cat.
+        assert_eq!(found[1].id,2);
+        add_question(&mut backend,"cat food for my cat",3,&tokenizer,&stopwords).unwrap();
+        let found = find_similar_in_database_with_matches(&mut backend,"Cat",&tokenizer,&stopwords).unwrap();
+        let cats = found.iter().find(|f|f.id==3).unwrap();
+        let matches : Vec<(Range<usize>,Range<usize>)> = cats.matches.iter().map(|m|(m.query.clone(),m.question.clone())).collect();
+        assert_eq!(matches,vec![(0..3,0..3),(0..3,16..19)]); // each once, though both the keyword and the word match both.
+    }
+
+    #[test]
+    fn spelling_correction() {
+        let (words,keywords,stopwords) = small_vocabulary("spelling_search");
+        let tokenizer = StandardTokenizer::new(&words,&keywords);
+        let mut backend = empty_database("spelling_search",&tokenizer,&stopwords);
+        add_question(&mut backend,"my kitten",1,&tokenizer,&stopwords).unwrap();
+        assert!(find_similar_in_database(&mut backend,"kitetn",&tokenizer,&stopwords).unwrap().is_empty());
+        let corrector = SpellingCorrector::new(&words,&keywords,Default::default());
+        let found = find_similar_in_database(&mut backend,"kitetn",&SpellingCorrection::new(tokenizer,&corrector),&stopwords).unwrap();
+        assert_eq!(found.len(),1);
+        assert_eq!(found[0].score,score_known(words.index("kitten").unwrap(),&words)*corrector.penalty());
+    }
+
+    #[test]
+    fn explanation() {
+        let (words,keywords,stopwords) = small_vocabulary("explanation");
+        let corrector = SpellingCorrector::new(&words,&keywords,Default::default());
+        let tokenizer = SpellingCorrection::new(StandardTokenizer::new(&words,&keywords),&corrector);
+        let explanation = explain_query("The kitetn coronavirus",&tokenizer,&stopwords);
+        let summary : Vec<(TokenKind,&str,Range<usize>,bool,bool)> = explanation.tokens.iter().map(|t|(t.kind,t.text.as_str(),t.span.clone(),t.alternative,t.indexed)).collect();
+        assert_eq!(summary,vec![(TokenKind::Word,"the",0..3,false,false),(TokenKind::Unknown,"kitetn",4..10,false,true),
+                                (TokenKind::Word,"kitten",4..10,true,true),(TokenKind::Keyword,"Covid",11..22,false,true)]);
+        let kitten = &explanation.tokens[2];
+        assert_eq!(kitten.corrected_from.as_deref(),Some("kitetn"));
+        assert_eq!(kitten.score,score_known(words.index("kitten").unwrap(),&words)*corrector.penalty());
+        assert_eq!(kitten.related.len(),3);
+        assert!(kitten.related.iter().any(|r|r.word=="cat" && !r.lemma && r.weight>0.0));
+        assert_eq!(explanation.tokens[0].score,0.0);
+    }
+
+    #[test]
+    fn hashtags() {
+        let (words,keywords,stopwords) = small_vocabulary("hashtags");
+        let tokenizer = StandardTokenizer::new(&words,&keywords);
+        let text = "#CovidVaccine #catdog @Cat_Dog #wherefore";
+        let tokenized = tokenizer.tokenize(text);
+        let explained : Vec<String> = tokenized.parts.iter().zip(&tokenized.spans).map(|(part,span)|format!("{} {}",tokenizer.explain(part),&text[span.clone()])).collect();
+        assert_eq!(explained,vec!["Hashtag #covidvaccine #CovidVaccine","Keyword 0 : Covid Covid","Word 7 : vaccine Vaccine",
+                                  "Hashtag #catdog #catdog","Word 3 : cat cat","Word 4 : dog dog",
+                                  "Mention @cat_dog @Cat_Dog",
+                                  "Hashtag #wherefore #wherefore","Word 6 : wherefore wherefore"]);
+        let mut backend = empty_database("hashtags",&tokenizer,&stopwords);
+        add_question(&mut backend,"Where is my cat?",1,&tokenizer,&stopwords).unwrap();
+        add_question(&mut backend,"#CatDog",2,&tokenizer,&stopwords).unwrap();
+        let found = find_similar_in_database(&mut backend,"#catdog",&tokenizer,&stopwords).unwrap();
+        assert_eq!(found.len(),2);
+        assert_eq!(found[0].id,2); // the whole hashtag matches too.
+    }
+
+    #[test]
+    fn typed_values() {
+        let (words,keywords,stopwords) = small_vocabulary("typed_values");
+        let tokenizer = StandardTokenizer::new(&words,&keywords);
+        let mut backend = empty_database("typed_values",&tokenizer,&stopwords);
+        add_question(&mut backend,"Why spend $5b on 3 vaccines?",1,&tokenizer,&stopwords).unwrap();
+        add_question(&mut backend,"Only 3 cats",2,&tokenizer,&stopwords).unwrap();
+        let found = find_similar_in_database(&mut backend,"five billion dollars for 3",&tokenizer,&stopwords).unwrap();
+        assert_eq!(found.len(),2);
+        assert_eq!((found[0].id,found[0].score),(1,6.0+1.0));
+        assert_eq!((found[1].id,found[1].score),(2,1.0)); // a shared number is not worth much.
+    }
+}
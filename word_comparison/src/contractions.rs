@@ -0,0 +1,79 @@
+//! Expand contractions such as "don't" to "do not", and remove possessive "'s", so "government's" is the word "government".
+//!
+//! This is done on normalized text (see [normalize](crate::normalize)), in which curly apostrophes are already
+//! ASCII apostrophes, while keeping track of where each part of the result came from in the original text.
+
+use std::ops::Range;
+use crate::normalize::{Normalization, NormalizedText};
+
+/// Contractions that can't be expanded by just changing the ending.
+const IRREGULAR : [(&str,&str);5] = [("won't","will not"),("shan't","shall not"),("ain't","is not"),("can't","can not"),("let's","let us")];
+
+/// Endings that are contractions, and what they stand for.
+const ENDINGS : [(&str,&str);6] = [("n't"," not"),("'re"," are"),("'ve"," have"),("'ll"," will"),("'d"," would"),("'m"," am")];
+
+/// Words for which a following "'s" means "is" rather than being possessive.
+const S_IS_IS : [&str;11] = ["it","that","what","he","she","there","here","who","where","how","when"];
+
+/// How to change a word : keep the first (return value) bytes, and replace the rest by the given text.
+fn expand_word(word:&str) -> Option<(usize,&'static str)> {
+    if let Some(&(_,expansion)) = IRREGULAR.iter().find(|(contraction,_)|*contraction==word) { return Some((0,expansion)); }
+    for (ending,expansion) in ENDINGS {
+        if word.len()>ending.len() && word.ends_with(ending) { return Some((word.len()-ending.len(),expansion)); }
+    }
+    if let Some(stem) = word.strip_suffix("'s").filter(|stem|!stem.is_empty()) {
+        return Some((stem.len(),if S_IS_IS.contains(&stem) { " is" } else { "" }));
+    }
+    if word.len()>2 && word.ends_with("s'") { return Some((word.len()-1,"")); } // plural possessive "governments'"
+    None
+}
+
+/// Expand the contractions and remove possessives in normalized text.
+/// A removed possessive is counted as part of the word before it for finding where text came from.
+pub fn expand_contractions(text:&NormalizedText) -> NormalizedText {
+    let mut res = NormalizedText::empty();
+    let s = text.text.as_str();
+    let mut pos = 0;
+    while pos<s.len() {
+        let word_len = s[pos..].find(|c:char|!(c.is_alphanumeric() || c=='\'')).unwrap_or(s.len()-pos);
+        if word_len==0 {
+            let char_len = s[pos..].chars().next().unwrap().len_utf8();
+            res.push_from(text,pos..pos+char_len);
+            pos+=char_len;
+            continue;
+        }
+        let word : Range<usize> = pos..pos+word_len;
+        match expand_word(&s[word.clone()]) {
+            Some((keep,expansion)) => {
+                res.push_from(text,word.start..word.start+keep);
+                let replaced = word.start+keep..word.end;
+                if expansion.is_empty() { res.extend_last_source(text.original_span(replaced).end); }
+                else { res.push_with_source(expansion,text.original_span(replaced)); }
+            }
+            None => res.push_from(text,word.clone()),
+        }
+        pos=word.end;
+    }
+    res
+}
+
+/// Normalize text in the way the tokenizer does : normalization, single spaces between words, and contractions expanded.
+pub fn normalize_for_tokenizing(normalization:Normalization,text:&str) -> NormalizedText {
+    expand_contractions(&normalization.normalize_tracking_offsets(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contractions_and_possessives() {
+        let original = "Don\u{2019}t the government's ministers' cats say it's what we'd do? Won't ScoMo's";
+        let normalized = normalize_for_tokenizing(Normalization::default(),original);
+        assert_eq!(normalized.text,"do not the government ministers cats say it is what we would do? will not scomo");
+        assert_eq!(&original[normalized.original_span(0..6)],"Don\u{2019}t");
+        assert_eq!(&original[normalized.original_span(11..21)],"government's");
+        assert_eq!(&original[normalized.original_span(3..6)],"n\u{2019}t");
+        assert_eq!(normalize_for_tokenizing(Normalization::default(),"O'Brien's d'Arcy 's").text,"o'brien d'arcy 's");
+    }
+}
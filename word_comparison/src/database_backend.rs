@@ -2,10 +2,12 @@ use std::borrow::Cow;
 use std::collections::HashSet;
 use serde::{Serialize,Deserialize};
 use crate::comparison_list::ScoredIDs;
-use crate::listed_keywords::{ListedKeywordIndex, ListedKeywords};
-use crate::sentences::{SentencePart, TokenizedSentence};
+use crate::listed_keywords::ListedKeywordIndex;
+use crate::sentences::SentencePart;
+use crate::tokenizer::Tokenizer;
 use crate::word::WordIndex;
-use crate::word_file::WordsInFile;
+use crate::stopwords::StopwordPolicy;
+use crate::typed_tokens::TypedValue;
 
 /// Some identifier used internally to define sentences. Could be a simple integer in a database table. Done separately in case the external word ID is long.
 #[derive(Copy, Clone,Eq, PartialEq,Debug,Hash,Serialize,Deserialize)]
@@ -20,18 +22,34 @@ pub trait WordComparisonDatabaseBackend {
     type ExternalQuestionId : Clone;
 
     /// Find sentences containing the listed word (one of the curated words)
-    fn sentences_containing_listed_word(&self,word:ListedKeywordIndex) -> anyhow::Result<Option<Cow<Vec<InternalQuestionId>>>>;
+    fn sentences_containing_listed_word(&self,word:ListedKeywordIndex) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>>;
     /// Find sentences containing a word in the general lexicon.
-    fn sentences_containing_general_lexicon_word(&self,word:WordIndex) -> anyhow::Result<Option<Cow<Vec<InternalQuestionId>>>>;
+    fn sentences_containing_general_lexicon_word(&self,word:WordIndex) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>>;
+    /// Find sentences containing a word in the general lexicon with the given lemma (see [WordsInFile::lemma](crate::word_file::WordsInFile::lemma)).
+    /// The default finds none, for backends that do not index lemmas.
+    fn sentences_containing_lemma(&self,_lemma:WordIndex) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>> { Ok(None) }
+    /// Find sentences containing a number, amount of money, date or the like with the same [TypedValue::key].
+    /// The default finds none, for backends that do not index typed values.
+    fn sentences_containing_typed_value(&self,_value:&TypedValue) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>> { Ok(None) }
     /// Find sentences containing a unknown word. Possibly a typo, possibly vital hashtag or jargon.
-    fn sentences_containing_unknown_word(&self,word:&str) -> anyhow::Result<Option<Cow<Vec<InternalQuestionId>>>>;
+    fn sentences_containing_unknown_word(&self,word:&str) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>>;
 
     /// For a sentence that has been divided up into tokens, record said tokens as associated with this sentence.
-    fn add_sentence_and_components<'a>(&mut self,external_id:Self::ExternalQuestionId,parsed:ParsedQuestion) -> anyhow::Result<InternalQuestionId>;
+    fn add_sentence_and_components(&mut self,external_id:Self::ExternalQuestionId,parsed:ParsedQuestion) -> anyhow::Result<InternalQuestionId>;
 
     /// Get all questions in the database. Could be slow! Just used for debugging.
     fn get_all_questions(&self) -> anyhow::Result<Vec<String>>;
 
+    /// Get the text of a question from its internal id. This is needed by [find_similar_in_database_with_matches](crate::comparison_list::find_similar_in_database_with_matches),
+    /// which fails with the default of None.
+    fn question_text(&self,_id:InternalQuestionId) -> anyhow::Result<Option<String>> { Ok(None) }
+
+    /// Get the texts of several questions, in the same order. The default calls [Self::question_text] for each; backends
+    /// where each call is a round trip should fetch them all at once.
+    fn question_texts(&self,ids:&[InternalQuestionId]) -> anyhow::Result<Vec<Option<String>>> {
+        ids.iter().map(|&id|self.question_text(id)).collect()
+    }
+
     /// Get the text associated with a question. Mainly used for debugging.
     fn lookup(&self,id:Self::ExternalQuestionId) -> anyhow::Result<Option<String>>;
 
@@ -48,24 +66,41 @@ pub struct ParsedQuestion {
     pub(crate) question : String,
     pub(crate) keywords : HashSet<ListedKeywordIndex>,
     pub(crate) known_words : HashSet<WordIndex>,
+    /// The lemmas of known_words, if the vocabulary has lemmas.
+    pub(crate) lemmas : HashSet<WordIndex>,
+    /// The [TypedValue::key] of each typed value.
+    pub(crate) typed_values : HashSet<String>,
+    /// Unknown words, and hashtags and mentions including the "#" or "@".
     pub(crate) unique_words : HashSet<String>,
 }
 
 
 
 impl ParsedQuestion {
-    pub fn new(question : String,words:&WordsInFile,keywords:&ListedKeywords) -> Self {
-        let tokenized = TokenizedSentence::tokenize(&question,words,keywords);
+    /// Tokenize the question. General vocabulary words that are stopwords are left out; the same policy should be used when searching.
+    pub fn new<T:Tokenizer>(question : String,tokenizer:&T,stopwords:&StopwordPolicy) -> Self {
+        let tokenized = tokenizer.tokenize_stored(&question);
         let mut keywords = HashSet::new();
         let mut known_words = HashSet::new();
         let mut unique_words = HashSet::new();
+        let mut lemmas = HashSet::new();
+        let mut typed_values = HashSet::new();
         for token in tokenized.parts {
             match token {
                 SentencePart::Listed(word) => {keywords.insert(word);}
-                SentencePart::Known(word) => { if word.0 > 400 { known_words.insert(word); }}
+                SentencePart::Known(word) => {
+                    let (words,local) = tokenizer.vocabulary(word);
+                    if !stopwords.is_stopword(local,words) {
+                        known_words.insert(word);
+                        if words.has_lemmas() { lemmas.insert(tokenizer.global_index(word,words.lemma(local))); }
+                    }
+                }
                 SentencePart::Unknown(word) => {unique_words.insert(word);}
+                SentencePart::Hashtag(tag) => {unique_words.insert(format!("#{}",tag));}
+                SentencePart::Mention(account) => {unique_words.insert(format!("@{}",account));}
+                SentencePart::Typed(value) => {typed_values.insert(value.key());}
             }
         }
-        ParsedQuestion { question, keywords, known_words, unique_words }
+        ParsedQuestion { question, keywords, known_words, lemmas, typed_values, unique_words }
     }
 }
@@ -0,0 +1,244 @@
+//! Read word vectors (embeddings) in the common formats they are distributed in.
+//!
+//! * GloVe : text, one word per line followed by its components, separated by spaces.
+//! * word2vec text, and fastText `.vec` : the same, but with a first line giving the number of words and dimensions.
+//! * word2vec binary : a text line giving the number of words and dimensions, then for each word, the word, a space, and the components as little endian f32.
+//!
+//! Other formats can be read by implementing [EmbeddingReader].
+//!
+//! Files are assumed to have the most common words first, as all the above do.
+//! Some words (e.g. in the cased GloVe downloads) contain spaces, so in text formats the word is taken to be everything before the last D fields, where D is the number of dimensions.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use crate::word::{MemoryWords, WordSource, WordVec, WordVecs};
+use crate::normalize::Normalization;
+
+/// A source of words and their vectors, most common first.
+pub trait EmbeddingReader {
+    /// The next word and its vector, or None if there are no more.
+    fn next_embedding(&mut self) -> std::io::Result<Option<(String,Vec<f64>)>>;
+}
+
+fn invalid_data(message:String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData,message)
+}
+
+/// Parse a "number_words dimensions" header line.
+fn parse_header(line:&str) -> Option<(usize,usize)> {
+    let mut fields = line.split_whitespace();
+    let res = (fields.next()?.parse().ok()?,fields.next()?.parse().ok()?);
+    if fields.next().is_some() { None } else { Some(res) }
+}
+
+/// Reads GloVe, word2vec text and fastText `.vec` files.
+pub struct TextEmbeddingReader<R:BufRead> {
+    lines : std::io::Lines<R>,
+    line_number : usize,
+    /// Known from the header or the first line.
+    dimensions : Option<usize>,
+}
+
+impl <R:BufRead> TextEmbeddingReader<R> {
+    /// If has_header, the first line is the number of words and dimensions, as in word2vec text and fastText `.vec` files.
+    pub fn new(reader:R,has_header:bool) -> std::io::Result<Self> {
+        let mut res = TextEmbeddingReader{ lines: reader.lines(), line_number: 0, dimensions: None };
+        if has_header {
+            let line = res.lines.next().transpose()?.unwrap_or_default();
+            res.line_number=1;
+            let (_,dimensions) = parse_header(&line).ok_or_else(||invalid_data(format!("Line 1 should be the number of words and dimensions, not {}",line)))?;
+            res.dimensions=Some(dimensions);
+        }
+        Ok(res)
+    }
+}
+
+impl <R:BufRead> EmbeddingReader for TextEmbeddingReader<R> {
+    fn next_embedding(&mut self) -> std::io::Result<Option<(String,Vec<f64>)>> {
+        loop {
+            let line = match self.lines.next() { Some(line) => line?, None => return Ok(None) };
+            self.line_number+=1;
+            let line = line.trim_end(); // fastText has a trailing space, and windows line endings may be present.
+            if line.is_empty() { continue; }
+            let fields : Vec<&str> = line.split(' ').collect();
+            let dimensions = *self.dimensions.get_or_insert(fields.len()-1);
+            if dimensions==0 || fields.len()<dimensions+1 { return Err(invalid_data(format!("Line {} has {} fields, expected a word followed by {} numbers",self.line_number,fields.len(),dimensions))); }
+            let split = fields.len()-dimensions;
+            let mut vec = Vec::with_capacity(dimensions);
+            for field in &fields[split..] {
+                vec.push(field.parse().map_err(|_|invalid_data(format!("Line {} has invalid number {}",self.line_number,field)))?);
+            }
+            return Ok(Some((fields[..split].join(" "),vec)));
+        }
+    }
+}
+
+/// Reads the word2vec binary format.
+pub struct Word2VecBinaryReader<R:BufRead> {
+    reader : R,
+    number_words : usize,
+    dimensions : usize,
+    words_read : usize,
+}
+
+impl <R:BufRead> Word2VecBinaryReader<R> {
+    pub fn new(mut reader:R) -> std::io::Result<Self> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let (number_words,dimensions) = parse_header(&line).ok_or_else(||invalid_data(format!("word2vec binary file should start with the number of words and dimensions, not {}",line.trim_end())))?;
+        Ok(Word2VecBinaryReader{ reader, number_words, dimensions, words_read: 0 })
+    }
+}
+
+impl <R:BufRead> EmbeddingReader for Word2VecBinaryReader<R> {
+    fn next_embedding(&mut self) -> std::io::Result<Option<(String,Vec<f64>)>> {
+        if self.words_read==self.number_words { return Ok(None); }
+        self.words_read+=1;
+        let mut word = vec![];
+        self.reader.read_until(b' ',&mut word)?;
+        if word.last()!=Some(&b' ') { return Err(invalid_data(format!("word2vec binary file ends in word {} of {}",self.words_read,self.number_words))); }
+        word.pop();
+        let word = String::from_utf8_lossy(&word).trim_start_matches('\n').to_string(); // the original writer puts a newline after each vector.
+        let mut buffer = vec![0u8;4*self.dimensions];
+        self.reader.read_exact(&mut buffer).map_err(|e|invalid_data(format!("word2vec binary file vector for word {} ({}) : {}",self.words_read,word,e)))?;
+        let vec = buffer.chunks_exact(4).map(|b|f32::from_le_bytes([b[0],b[1],b[2],b[3]]) as f64).collect();
+        Ok(Some((word,vec)))
+    }
+}
+
+/// A file format for word vectors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbeddingFormat {
+    Glove,
+    /// word2vec text format, also used by fastText `.vec` files.
+    Word2VecText,
+    Word2VecBinary,
+}
+
+impl EmbeddingFormat {
+    /// Guess the format from the file name and first line. `.bin` files are word2vec binary, otherwise
+    /// a file whose first line is two numbers is word2vec text, and anything else is GloVe.
+    pub fn guess<P:AsRef<Path>>(path:P) -> std::io::Result<Self> {
+        if path.as_ref().extension().map(|e|e=="bin").unwrap_or(false) { return Ok(EmbeddingFormat::Word2VecBinary); }
+        let mut first_line = String::new();
+        BufReader::new(File::open(path)?).read_line(&mut first_line)?;
+        Ok(if parse_header(&first_line).is_some() { EmbeddingFormat::Word2VecText } else { EmbeddingFormat::Glove })
+    }
+
+    /// Open a file in this format.
+    pub fn open<P:AsRef<Path>>(self,path:P) -> std::io::Result<Box<dyn EmbeddingReader>> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(match self {
+            EmbeddingFormat::Glove => Box::new(TextEmbeddingReader::new(reader,false)?),
+            EmbeddingFormat::Word2VecText => Box::new(TextEmbeddingReader::new(reader,true)?),
+            EmbeddingFormat::Word2VecBinary => Box::new(Word2VecBinaryReader::new(reader)?),
+        })
+    }
+}
+
+/// Choices about what to do with the words read.
+#[derive(Clone, Debug, Default)]
+pub struct EmbeddingOptions {
+    /// If present, stop after this many words.
+    pub max_words : Option<usize>,
+    /// The input has distinct upper and lower case words (e.g. "Apple" and "apple"). Convert them to lower case,
+    /// with each word's vector being the mean of its case variants' vectors and its position that of the most common variant.
+    pub cased : bool,
+    /// If present, normalize each word (which also converts to lower case), merging words that become the same as for cased.
+    pub normalization : Option<Normalization>,
+}
+
+/// Read all the words from an [EmbeddingReader]. Repeated words (after lowercasing or normalizing) are merged if cased or normalizing, otherwise all but the first are ignored.
+pub fn read_embeddings<R:EmbeddingReader+?Sized>(reader:&mut R,options:&EmbeddingOptions) -> std::io::Result<(MemoryWords,WordVecs)> {
+    let mut words = MemoryWords::default();
+    // sum of the vectors of all variants, and the number of variants.
+    let mut sums : Vec<(Vec<f64>,usize)> = vec![];
+    while let Some((word,vec)) = reader.next_embedding()? {
+        if let Some((first,_)) = sums.first() {
+            if first.len()!=vec.len() { return Err(invalid_data(format!("Vector for {} has {} dimensions but the first has {}",word,vec.len(),first.len()))); }
+        }
+        let merge = options.cased || options.normalization.is_some();
+        let word = match options.normalization {
+            Some(normalization) => normalization.normalize(&word),
+            None if options.cased => word.to_lowercase(),
+            None => word,
+        };
+        match words.index(&word) {
+            Some(existing) if merge => {
+                let (sum,count) = &mut sums[existing.0 as usize];
+                for (total,v) in sum.iter_mut().zip(vec) { *total+=v; }
+                *count+=1;
+            }
+            Some(_) => {}
+            None => {
+                if options.max_words==Some(words.len()) {
+                    if merge { continue; } else { break; } // later words may still be variants of earlier ones.
+                }
+                words.add(&word);
+                sums.push((vec,1));
+            }
+        }
+    }
+    let mut wordvecs = WordVecs::default();
+    for (sum,count) in sums {
+        wordvecs.push(WordVec::new(sum.into_iter().map(|v|v/count as f64).collect()));
+    }
+    Ok((words,wordvecs))
+}
+
+/// Read a file of word vectors in the given format. Errors mention the file.
+pub fn read_embedding_file<P:AsRef<Path>>(path:P,format:EmbeddingFormat,options:&EmbeddingOptions) -> std::io::Result<(MemoryWords,WordVecs)> {
+    let path = path.as_ref();
+    let add_path = |e:std::io::Error|std::io::Error::new(e.kind(),format!("{} : {}",path.display(),e));
+    let mut reader = format.open(path).map_err(add_path)?;
+    read_embeddings(&mut *reader,options).map_err(add_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word::WordIndex;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn formats_give_same_vectors() {
+        let options = EmbeddingOptions::default();
+        let (words,_) = read_embeddings(&mut TextEmbeddingReader::new("the 1 0\nnew york 0.5 0.5\n".as_bytes(),false).unwrap(),&options).unwrap();
+        assert_eq!(words.word(WordIndex(1)),"new york");
+        let glove = "The 1 0\nthe 0 1\nnew_york 0.5 0.5\n"; // the binary format cannot have spaces in words.
+        let (words,wordvecs) = read_embeddings(&mut TextEmbeddingReader::new(glove.as_bytes(),false).unwrap(),&options).unwrap();
+        assert_eq!(words.len(),3);
+        let fasttext = "3 2\nThe 1 0 \nthe 0 1 \nnew_york 0.5 0.5 \n";
+        let (words2,wordvecs2) = read_embeddings(&mut TextEmbeddingReader::new(fasttext.as_bytes(),true).unwrap(),&options).unwrap();
+        let mut binary = b"3 2\n".to_vec();
+        for (word,vec) in [("The",[1.0f32,0.0]),("the",[0.0,1.0]),("new_york",[0.5,0.5])] {
+            binary.extend_from_slice(word.as_bytes());
+            binary.push(b' ');
+            for v in vec { binary.extend_from_slice(&v.to_le_bytes()); }
+            binary.push(b'\n');
+        }
+        let path = temp_path("embeddings.bin");
+        std::fs::write(&path,&binary).unwrap();
+        assert_eq!(EmbeddingFormat::guess(&path).unwrap(),EmbeddingFormat::Word2VecBinary);
+        let (words3,wordvecs3) = read_embedding_file(&path,EmbeddingFormat::Word2VecBinary,&options).unwrap();
+        for i in words.all_indices() {
+            assert_eq!(words.word(i),words2.word(i));
+            assert_eq!(words.word(i),words3.word(i));
+            assert_eq!(wordvecs.get(i).values(),wordvecs2.get(i).values());
+            assert_eq!(wordvecs.get(i).values(),wordvecs3.get(i).values());
+        }
+        let (cased,cased_vecs) = read_embeddings(&mut TextEmbeddingReader::new(glove.as_bytes(),false).unwrap(),&EmbeddingOptions{cased:true,max_words:Some(1),normalization:None}).unwrap();
+        assert_eq!(cased.len(),1);
+        assert_eq!(cased_vecs.get(WordIndex(0)).values(),&[0.5,0.5]);
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        let read = |text:&str| read_embeddings(&mut TextEmbeddingReader::new(text.as_bytes(),false).unwrap(),&EmbeddingOptions::default());
+        assert!(read("a 1 2\nb 1 x\n").err().unwrap().to_string().contains("Line 2"));
+        assert!(read("a 1 2\nb 1\n").is_err());
+        assert!(read("a 1 2\nb\n").is_err());
+        assert!(TextEmbeddingReader::new("a 1 2\n".as_bytes(),true).is_err());
+    }
+}
@@ -13,9 +13,11 @@ use std::str::FromStr;
 use anyhow::anyhow;
 use crate::comparison_list::ScoredIDs;
 use crate::database_backend::{InternalQuestionId, ParsedQuestion, WordComparisonDatabaseBackend};
-use crate::listed_keywords::{ListedKeywordIndex, ListedKeywords};
+use crate::listed_keywords::ListedKeywordIndex;
+use crate::tokenizer::Tokenizer;
 use crate::word::WordIndex;
-use crate::word_file::WordsInFile;
+use crate::stopwords::StopwordPolicy;
+use crate::typed_tokens::TypedValue;
 
 pub struct FlatfileDatabaseBackend<Q : Clone+Display> {
     filename : PathBuf,
@@ -23,26 +25,36 @@ pub struct FlatfileDatabaseBackend<Q : Clone+Display> {
     external_ids : Vec<Q>,
     containing_keyword : HashMap<ListedKeywordIndex,Vec<InternalQuestionId>>,
     containing_known_word : HashMap<WordIndex,Vec<InternalQuestionId>>,
+    containing_lemma : HashMap<WordIndex,Vec<InternalQuestionId>>,
+    containing_typed : HashMap<String,Vec<InternalQuestionId>>,
     containing_unique : HashMap<String,Vec<InternalQuestionId>>,
 }
 
 impl <Q : Clone+Display+PartialEq+FromStr> WordComparisonDatabaseBackend for FlatfileDatabaseBackend<Q> {
     type ExternalQuestionId = Q;
 
-    fn sentences_containing_listed_word(&self, word: ListedKeywordIndex) -> anyhow::Result<Option<Cow<Vec<InternalQuestionId>>>> {
-        Ok(self.containing_keyword.get(&word).map(|v|Cow::Borrowed(v)))
+    fn sentences_containing_listed_word(&self, word: ListedKeywordIndex) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>> {
+        Ok(self.containing_keyword.get(&word).map(Cow::Borrowed))
     }
 
-    fn sentences_containing_general_lexicon_word(&self, word: WordIndex) -> anyhow::Result<Option<Cow<Vec<InternalQuestionId>>>> {
-        Ok(self.containing_known_word.get(&word).map(|v|Cow::Borrowed(v)))
+    fn sentences_containing_general_lexicon_word(&self, word: WordIndex) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>> {
+        Ok(self.containing_known_word.get(&word).map(Cow::Borrowed))
     }
 
-    fn sentences_containing_unknown_word(&self, word: &str) -> anyhow::Result<Option<Cow<Vec<InternalQuestionId>>>> {
-        Ok(self.containing_unique.get(word).map(|v|Cow::Borrowed(v)))
+    fn sentences_containing_lemma(&self, lemma: WordIndex) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>> {
+        Ok(self.containing_lemma.get(&lemma).map(Cow::Borrowed))
     }
 
-    fn add_sentence_and_components<'a>(&mut self, external_id: Self::ExternalQuestionId,parsed:ParsedQuestion) -> anyhow::Result<InternalQuestionId> {
-        let mut file = OpenOptions::new().create(true).write(true).append(true).open(&self.filename)?;
+    fn sentences_containing_typed_value(&self, value: &TypedValue) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>> {
+        Ok(self.containing_typed.get(&value.key()).map(Cow::Borrowed))
+    }
+
+    fn sentences_containing_unknown_word(&self, word: &str) -> anyhow::Result<Option<Cow<'_,Vec<InternalQuestionId>>>> {
+        Ok(self.containing_unique.get(word).map(Cow::Borrowed))
+    }
+
+    fn add_sentence_and_components(&mut self, external_id: Self::ExternalQuestionId,parsed:ParsedQuestion) -> anyhow::Result<InternalQuestionId> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.filename)?;
         writeln!(file, "{}\t{}",external_id,parsed.question.replace('\n'," "))?;
         Ok(self.add_work(parsed,external_id))
     }
@@ -50,7 +62,10 @@ impl <Q : Clone+Display+PartialEq+FromStr> WordComparisonDatabaseBackend for Fla
 
     /// Get all questions in the database. Could be slow!
     fn get_all_questions(&self) -> anyhow::Result<Vec<String>> {
-        Ok(self.questions.iter().map(|q|q.clone()).collect())
+        Ok(self.questions.to_vec())
+    }
+    fn question_text(&self,id:InternalQuestionId) -> anyhow::Result<Option<String>> {
+        Ok(self.questions.get(id.0 as usize).cloned())
     }
     /// Get the text associated with a question. Very inefficient! But this is just for debugging.
     fn lookup(&self,id:Self::ExternalQuestionId) -> anyhow::Result<Option<String>> {
@@ -67,6 +82,8 @@ impl <Q : Clone+Display+PartialEq+FromStr> WordComparisonDatabaseBackend for Fla
         self.external_ids.clear();
         self.containing_keyword.clear();
         self.containing_known_word.clear();
+        self.containing_lemma.clear();
+        self.containing_typed.clear();
         self.containing_unique.clear();
         if Path::new(&self.filename).exists() { remove_file(&self.filename)? };
         Ok(())
@@ -79,7 +96,7 @@ impl <Q : Clone+Display+FromStr> FlatfileDatabaseBackend<Q> {
     fn add_work(&mut self, question:ParsedQuestion,external_id:Q) -> InternalQuestionId {
         let id = InternalQuestionId(self.questions.len() as u32);
         fn add<K>(entry:Entry<K,Vec<InternalQuestionId>>,id:InternalQuestionId) {
-            let v = entry.or_insert_with(||vec![]);
+            let v = entry.or_default();
             if v.is_empty() || id!= *v.last().unwrap() { // make sure the same id is not included twice.
                 v.push(id);
             }
@@ -89,11 +106,17 @@ impl <Q : Clone+Display+FromStr> FlatfileDatabaseBackend<Q> {
         }
         for &word in &question.known_words {
             add(self.containing_known_word.entry(word),id);
-            self.containing_known_word.entry(word).or_insert_with(||vec![]).push(id);
+            self.containing_known_word.entry(word).or_default().push(id);
+        }
+        for &lemma in &question.lemmas {
+            add(self.containing_lemma.entry(lemma),id);
+        }
+        for key in &question.typed_values {
+            add(self.containing_typed.entry(key.clone()),id);
         }
         for word in &question.unique_words {
             add(self.containing_unique.entry(word.clone()),id);
-            self.containing_unique.entry(word.clone()).or_insert_with(||vec![]).push(id);
+            self.containing_unique.entry(word.clone()).or_default().push(id);
         }
         self.questions.push(question.question);
         self.external_ids.push(external_id);
@@ -101,7 +124,7 @@ impl <Q : Clone+Display+FromStr> FlatfileDatabaseBackend<Q> {
     }
 
     /// Get a new database, initialised from text file if it exists.
-    pub fn new<P:AsRef<Path>+ std::convert::AsRef<std::ffi::OsStr>>(path:P,words:&WordsInFile,keywords:&ListedKeywords) -> anyhow::Result<Self>
+    pub fn new<P:AsRef<Path>+ std::convert::AsRef<std::ffi::OsStr>,T:Tokenizer>(path:P,tokenizer:&T,stopwords:&StopwordPolicy) -> anyhow::Result<Self>
         where <Q as FromStr>::Err: std::error::Error + Send + Sync + 'static {
         let mut res = FlatfileDatabaseBackend{
             filename : PathBuf::from(&path),
@@ -109,23 +132,60 @@ impl <Q : Clone+Display+FromStr> FlatfileDatabaseBackend<Q> {
             external_ids: vec![],
             containing_keyword: Default::default(),
             containing_known_word: Default::default(),
+            containing_lemma: Default::default(),
+            containing_typed: Default::default(),
             containing_unique: Default::default()
         };
-        if let Ok(file) = File::open(path) {
-            for line in std::io::BufReader::new(file).lines() {
+        res.read_new_questions(tokenizer,stopwords)?;
+        Ok(res)
+    }
+
+    /// Add the questions in the file after the ones already loaded, returning how many there were.
+    /// The file is only ever appended to, so this brings a database read from an earlier state of the file up to date.
+    pub fn read_new_questions<T:Tokenizer>(&mut self,tokenizer:&T,stopwords:&StopwordPolicy) -> anyhow::Result<usize>
+        where <Q as FromStr>::Err: std::error::Error + Send + Sync + 'static {
+        let mut added = 0;
+        if let Ok(file) = File::open(&self.filename) {
+            for line in std::io::BufReader::new(file).lines().skip(self.questions.len()) {
                 if let Some((external_id,question)) = line?.split_once('\t') {
                     let external_id = Q::from_str(external_id)?;
-                    let parsed = ParsedQuestion::new(question.to_owned(),words,keywords);
-                    res.add_work(parsed,external_id);
+                    let parsed = ParsedQuestion::new(question.to_owned(),tokenizer,stopwords);
+                    self.add_work(parsed,external_id);
+                    added+=1;
                 } else {
                     return Err(anyhow!("Line in wrong format"))
                 }
             }
         }
-        Ok(res)
+        Ok(added)
     }
 
     pub fn len(&self) -> usize { self.questions.len() }
+    pub fn is_empty(&self) -> bool { self.questions.is_empty() }
+}
 
-
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comparison_list::add_question;
+    use crate::tokenizer::StandardTokenizer;
+    use crate::word_file::WordsInFile;
+    use crate::test_util::{keywords_from, small_word_file, temp_path};
+
+    #[test]
+    fn questions_added_by_another_copy() {
+        let words = WordsInFile::read_word_file(small_word_file("flatfile")).unwrap();
+        let keywords = keywords_from("flatfile","Covid,Coronavirus\n");
+        let tokenizer = StandardTokenizer::new(&words,&keywords);
+        let stopwords = StopwordPolicy::Rank(2);
+        let path = temp_path("flatfile_database.txt");
+        let _ = std::fs::remove_file(&path);
+        let mut first = FlatfileDatabaseBackend::<u32>::new(&path,&tokenizer,&stopwords).unwrap();
+        add_question(&mut first,"my cat",1,&tokenizer,&stopwords).unwrap();
+        let mut second = FlatfileDatabaseBackend::<u32>::new(&path,&tokenizer,&stopwords).unwrap();
+        add_question(&mut first,"my dog",2,&tokenizer,&stopwords).unwrap();
+        assert_eq!(second.read_new_questions(&tokenizer,&stopwords).unwrap(),1);
+        assert_eq!(second.get_all_questions().unwrap(),vec!["my cat".to_string(),"my dog".to_string()]);
+        assert_eq!(second.read_new_questions(&tokenizer,&stopwords).unwrap(),0);
+    }
+}
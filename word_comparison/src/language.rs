@@ -0,0 +1,100 @@
+//! Guess which language a question is written in, so it can be tokenized with the right general vocabulary.
+//!
+//! This only needs to tell apart the languages questions are actually asked in, so it goes by the scripts the letters
+//! are written in rather than a statistical model : Chinese characters, Arabic letters, and Latin letters, which are
+//! Vietnamese if enough of the words use letters only Vietnamese has (such as "ư" or "ạ").
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Add new languages at the end, and never reorder them : the position of a language fixes its range of word indices
+/// (see [Language::first_word_index]), which may be stored in databases.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Language {
+    English = 0,
+    Vietnamese = 1,
+    Chinese = 2,
+    Arabic = 3,
+}
+
+/// How many word indices each language has, far more than the words in any vocabulary.
+pub const WORD_INDICES_PER_LANGUAGE : u32 = 1<<28;
+
+impl Language {
+    pub const ALL : [Language;4] = [Language::English,Language::Vietnamese,Language::Chinese,Language::Arabic];
+
+    /// The ISO 639-1 code, such as "en".
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Vietnamese => "vi",
+            Language::Chinese => "zh",
+            Language::Arabic => "ar",
+        }
+    }
+
+    pub fn from_code(code:&str) -> Option<Language> {
+        Language::ALL.iter().copied().find(|language|language.code()==code)
+    }
+
+    /// The first word index of this language's range, when the vocabularies of several languages are used together
+    /// (see [MultilingualTokenizer](crate::multilingual::MultilingualTokenizer)). The ranges are fixed, so extending
+    /// one vocabulary or adding another language does not change the indices of any other language.
+    pub fn first_word_index(self) -> u32 { self as u32*WORD_INDICES_PER_LANGUAGE }
+
+    /// The language whose range of word indices contains the index.
+    pub fn of_word_index(index:u32) -> Option<Language> {
+        Language::ALL.get((index/WORD_INDICES_PER_LANGUAGE) as usize).copied()
+    }
+}
+
+/// Whether the character is from a script written without spaces between words (Chinese characters, and Japanese kana),
+/// so words have to be found by [dictionary segmentation](crate::sentences::TokenizedSentence::tokenize) instead.
+pub fn is_unspaced(c:char) -> bool {
+    matches!(c,'\u{3040}'..='\u{30FF}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}' | '\u{20000}'..='\u{2FA1F}')
+}
+
+fn is_arabic(c:char) -> bool {
+    matches!(c,'\u{0600}'..='\u{06FF}' | '\u{0750}'..='\u{077F}' | '\u{08A0}'..='\u{08FF}' | '\u{FB50}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFF}')
+}
+
+/// Latin letters that are used in Vietnamese but not (or very rarely) in other languages written in Latin letters.
+fn is_vietnamese(c:char) -> bool {
+    matches!(c,'ă' | 'Ă' | 'đ' | 'Đ' | 'ơ' | 'Ơ' | 'ư' | 'Ư' | '\u{1EA0}'..='\u{1EF9}')
+}
+
+/// A word is Vietnamese if it has a letter only Vietnamese uses. The text is Vietnamese if at least this fraction of its
+/// Latin words are, so an English question mentioning "Nguyễn" is still English.
+const VIETNAMESE_WORD_FRACTION : f64 = 0.25;
+
+/// Guess the language of the text. Text with no letters at all is English.
+pub fn detect_language(text:&str) -> Language {
+    let text : String = text.nfc().collect(); // so "ư" typed as "u" and a combining horn is one character.
+    let (mut unspaced,mut arabic,mut latin) = (0,0,0);
+    for c in text.chars().filter(|c|c.is_alphabetic()) {
+        if is_unspaced(c) { unspaced+=1; } else if is_arabic(c) { arabic+=1; } else { latin+=1; }
+    }
+    if unspaced>0 && unspaced>=arabic && unspaced*2>=latin { return Language::Chinese; } // each Chinese character carries as much as a few Latin letters.
+    if arabic>latin { return Language::Arabic; }
+    let latin_words : Vec<&str> = text.split(|c:char|!c.is_alphabetic()).filter(|word|!word.is_empty() && !word.chars().any(|c|is_unspaced(c) || is_arabic(c))).collect();
+    let vietnamese_words = latin_words.iter().filter(|word|word.chars().any(is_vietnamese)).count();
+    if vietnamese_words>0 && vietnamese_words as f64>=VIETNAMESE_WORD_FRACTION*latin_words.len() as f64 { Language::Vietnamese } else { Language::English }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detection() {
+        assert_eq!(detect_language("Why is the vaccine rollout so slow?"),Language::English);
+        assert_eq!(detect_language("Did Prime Minister Nguyễn Xuân Phúc meet the Treasurer about the budget?"),Language::English);
+        assert_eq!(detect_language("Tại sao chương trình tiêm vắc-xin lại chậm như vậy?"),Language::Vietnamese);
+        assert_eq!(detect_language("为什么疫苗接种这么慢？"),Language::Chinese);
+        assert_eq!(detect_language("新冠 vaccine 什么时候到？"),Language::Chinese);
+        assert_eq!(detect_language("لماذا طرح اللقاح بطيء جدا؟"),Language::Arabic);
+        assert_eq!(detect_language("$5b?"),Language::English);
+        assert_eq!(Language::from_code("zh"),Some(Language::Chinese));
+        assert_eq!(Language::of_word_index(Language::Arabic.first_word_index()+5),Some(Language::Arabic));
+        assert_eq!(Language::of_word_index(Language::Vietnamese.first_word_index()-1),Some(Language::English));
+    }
+}
@@ -0,0 +1,58 @@
+//! Group inflections of a word (e.g. "vaccinated", "vaccinates", "vaccinating") under a single lemma ("vaccinate").
+//!
+//! Word vectors only list other inflections as synonyms if they happen to be close, so questions
+//! are also indexed and matched by the lemma of each general vocabulary word. The lemma table comes from a text
+//! file with one word per line, followed by whitespace, followed by its lemma:
+//! ```text
+//! # comments start with a hash
+//! vaccinated vaccinate
+//! vaccinations vaccination
+//! ```
+//! Words not in the file are their own lemma.
+//!
+//! The table can be stored in the vocabulary file when it is built (see [WordFileOptions](crate::word_file::WordFileOptions)),
+//! or loaded next to it (see [WordsInFile::apply_lemmas](crate::word_file::WordsInFile::apply_lemmas)).
+
+use std::io::BufRead;
+use std::path::Path;
+use crate::normalize::Normalization;
+use crate::word::{WordIndex, WordSource};
+
+#[derive(Clone, Debug, Default)]
+pub struct Lemmas {
+    /// (word, lemma) pairs, as written.
+    pub pairs : Vec<(String,String)>,
+}
+
+impl Lemmas {
+    pub const STD_LOCATION : &'static str = "Lemmas.txt";
+
+    /// Load the table. The words are normalized when [resolved](Self::resolve) against a vocabulary.
+    pub fn load<P:AsRef<Path>>(path:P) -> std::io::Result<Self> {
+        let mut pairs = vec![];
+        for (line_number,line) in std::io::BufReader::new(std::fs::File::open(path)?).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+            let fields : Vec<&str> = line.split_whitespace().collect();
+            if fields.len()!=2 { return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,format!("Lemmas line {} should be a word followed by its lemma",line_number+1))); }
+            pairs.push((fields[0].to_string(),fields[1].to_string()));
+        }
+        Ok(Lemmas{pairs})
+    }
+
+    /// Convert to a table giving the lemma of each word in the vocabulary, after normalizing them in the same way as the vocabulary,
+    /// and the number of pairs ignored as either word is not in the vocabulary.
+    /// Lemma lists are usually much larger than the vocabulary, so ignored pairs are normal.
+    pub fn resolve<W:WordSource>(&self,words:&W,normalization:Normalization) -> (Vec<WordIndex>,usize) {
+        let mut table : Vec<WordIndex> = words.all_indices().collect();
+        let mut ignored = 0;
+        for (word,lemma) in &self.pairs {
+            match (words.index(&normalization.normalize(word)),words.index(&normalization.normalize(lemma))) {
+                (Some(word),Some(lemma)) => table[word.0 as usize]=lemma,
+                _ => ignored+=1,
+            }
+        }
+        (table,ignored)
+    }
+}
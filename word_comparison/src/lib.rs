@@ -1,12 +1,30 @@
 pub mod word;
+pub mod normalize;
+pub mod contractions;
+pub mod embedding_reader;
 pub mod word_file;
+pub mod reloadable_word_file;
 pub mod near_words;
+pub mod synonym_search;
+pub mod synonym_overlay;
+pub mod lemmas;
+pub mod phrases;
+pub mod vocabulary_extension;
 pub mod sentences;
+pub mod tokenizer;
+pub mod language;
+pub mod multilingual;
+pub mod spelling;
+pub mod typed_tokens;
+pub mod stopwords;
 pub mod listed_keywords;
 pub mod comparison_list;
 pub mod database_backend;
 pub mod flatfile_database_backend;
 
+#[cfg(test)]
+mod test_util;
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -1,8 +1,15 @@
 //! Some listed keywords, saying that "Covid", "Covid-19", "Covid 19" and "Cononavirus" are all the same concept.
+//! The aliases can be in several languages (such as "新冠" for Covid), so questions in different languages match.
 
 
 use std::path::Path;
 use csv::ReaderBuilder;
+use crate::normalize::Normalization;
+use crate::contractions::normalize_for_tokenizing;
+use crate::language::is_unspaced;
+use aho_corasick::{Anchored, MatchKind, StartKind};
+use aho_corasick::automaton::Automaton;
+use aho_corasick::dfa::DFA;
 
 pub struct ListedKeyword(pub Vec<String>);
 
@@ -10,12 +17,21 @@ pub struct ListedKeyword(pub Vec<String>);
 pub struct ListedKeywordIndex(pub usize);
 
 pub struct ListedKeywords {
-    keywords : Vec<ListedKeyword>
+    keywords : Vec<ListedKeyword>,
+    /// An anchored automaton matching all the aliases of all the keywords after normalization.
+    matcher : DFA,
+    /// Each pattern in the matcher, and its keyword.
+    aliases : Vec<(String,ListedKeywordIndex)>,
 }
 
 impl ListedKeywords {
     pub const STD_LOCATION : &'static str = "ListedKeywords.csv";
+    /// Load with the default normalization. Use [Self::load_with_normalization] with [WordsInFile::normalization](crate::word_file::WordsInFile::normalization) if the vocabulary used a different one.
     pub fn load<P:AsRef<Path>>(path:P) -> std::io::Result<Self> {
+        Self::load_with_normalization(path,Normalization::default())
+    }
+
+    pub fn load_with_normalization<P:AsRef<Path>>(path:P,normalization:Normalization) -> std::io::Result<Self> {
         let mut keywords = vec![];
         let mut reader = ReaderBuilder::new().flexible(true).has_headers(false).from_path(path)?;
         for result in reader.records() {
@@ -23,17 +39,60 @@ impl ListedKeywords {
             let keyword = ListedKeyword(record.iter().map(|s|s.to_string()).collect());
             keywords.push(keyword);
         }
-        Ok(ListedKeywords{keywords})
+        Self::new(keywords,normalization)
+    }
+
+    /// Match the keywords, each a list of aliases with the canonical one first, after normalizing them.
+    fn new(keywords:Vec<ListedKeyword>,normalization:Normalization) -> std::io::Result<Self> {
+        let normalized : Vec<ListedKeyword> = keywords.iter().map(|k|ListedKeyword(k.0.iter().map(|s|normalize_for_tokenizing(normalization,s).text).collect())).collect();
+        let mut aliases = vec![];
+        for (i,keyword) in normalized.into_iter().enumerate() {
+            for alias in keyword.0.into_iter().filter(|alias|!alias.is_empty()) {
+                aliases.push((alias,ListedKeywordIndex(i)));
+            }
+        }
+        let matcher = DFA::builder().match_kind(MatchKind::Standard).start_kind(StartKind::Anchored).ascii_case_insensitive(true).build(aliases.iter().map(|(alias,_)|alias))
+            .map_err(|e|std::io::Error::new(std::io::ErrorKind::InvalidData,e))?;
+        Ok(ListedKeywords{keywords,matcher,aliases})
     }
 
-    /// find a keyword that s starts with, returning the found keyword and the length consumed.
+    /// find a keyword that s (which should already be normalized) starts with, returning the found keyword and the length consumed.
+    ///
+    /// The longest alias of any keyword that ends at a word boundary is used, so "covid" does not match the start of
+    /// "covidsafe" and "prime minister" is preferred to "prime". A possessive "'s" after the alias is included
+    /// in the length consumed. If several keywords have the same alias, the first is used.
+    ///
+    /// The automaton is walked one byte at a time along s, so this takes time proportional to the length of the longest
+    /// alias matched, not the number of keywords.
     pub fn find_keyword_starting(&self,s:&str) -> Option<(ListedKeywordIndex,usize)> {
-        for i in 0..self.keywords.len() {
-            if let Some(used) = self.keywords[i].find_keyword_starting(s) {
-                return Some((ListedKeywordIndex(i),used))
+        let mut best : Option<(ListedKeywordIndex,usize)> = None;
+        let mut state = self.matcher.start_state(Anchored::Yes).ok()?;
+        for (i,&byte) in s.as_bytes().iter().enumerate() {
+            state = self.matcher.next_state(Anchored::Yes,state,byte);
+            if self.matcher.is_dead(state) { break; }
+            if self.matcher.is_match(state) {
+                let len = i+1;
+                for m in 0..self.matcher.match_len(state) {
+                    let pattern = self.matcher.match_pattern(state,m);
+                    if self.matcher.pattern_len(pattern)!=len { continue; } // a suffix of what has been read, not a prefix of s.
+                    if let Some(used) = at_word_boundary(s,len) {
+                        let keyword = self.aliases[pattern.as_usize()].1;
+                        if best.map(|(best_keyword,best_len)|best_len<used || (best_len==used && keyword.0<best_keyword.0)).unwrap_or(true) { best=Some((keyword,used)); }
+                    }
+                }
             }
         }
-        None
+        best
+    }
+
+    /// The keyword that the whole of s (which should already be normalized) refers to, if any.
+    pub fn keyword(&self,s:&str) -> Option<ListedKeywordIndex> {
+        self.find_keyword_starting(s).filter(|&(_,used)|used==s.len()).map(|(keyword,_)|keyword)
+    }
+
+    /// All the ways of referring to all the keywords, after normalization.
+    pub fn normalized_aliases(&self) -> impl Iterator<Item=(&str,ListedKeywordIndex)> {
+        self.aliases.iter().map(|(alias,keyword)|(alias.as_str(),*keyword))
     }
 
     /// Get a canonical example of this word.
@@ -42,14 +101,49 @@ impl ListedKeywords {
     }
 }
 
+/// If an alias s[..len] ends at a word boundary, the length to consume, including any possessive "'s".
+/// Scripts written without spaces, such as Chinese, have a boundary between every character.
+fn at_word_boundary(s:&str,len:usize) -> Option<usize> {
+    let is_word_character = |c:char| c.is_alphanumeric() && !is_unspaced(c);
+    let ends_in_word = s[..len].chars().next_back().map(is_word_character).unwrap_or(false);
+    let starts_word = |pos:usize| s[pos..].chars().next().map(is_word_character).unwrap_or(false);
+    if !ends_in_word { Some(len) }
+    else if !starts_word(len) {
+        if s[len..].starts_with("'s") && !starts_word(len+2) { Some(len+2) } else { Some(len) }
+    } else { None }
+}
+
 impl ListedKeyword {
-    /// find a keyword that s starts with, returning the length consumed.
+    /// find an alias of this keyword that s (which should already be normalized) starts with, returning the length consumed.
+    /// This builds a matcher on every call; match against all the keywords at once with [ListedKeywords::find_keyword_starting] instead.
+    #[deprecated(note="use ListedKeywords::find_keyword_starting")]
     pub fn find_keyword_starting(&self,s:&str) -> Option<usize> {
-        for word in &self.0 {
-            if s.len()>=word.len() {
-                if s.as_bytes()[..word.len()].eq_ignore_ascii_case(word.as_bytes()) { return Some(word.len()) }
-            }
-        }
-        None
+        let single = ListedKeywords::new(vec![ListedKeyword(self.0.clone())],Normalization::default()).ok()?;
+        single.find_keyword_starting(s).map(|(_,used)|used)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::keywords_from;
+
+    #[test]
+    fn longest_match_at_word_boundary() {
+        let keywords = keywords_from("boundary","Covid,Coronavirus\nPrime,Primer\nPrime Minister,ScoMo\nCOVIDSafe\n");
+        assert_eq!(keywords.find_keyword_starting("covid vaccines"),Some((ListedKeywordIndex(0),5)));
+        assert_eq!(keywords.find_keyword_starting("covid."),Some((ListedKeywordIndex(0),5)));
+        assert_eq!(keywords.find_keyword_starting("covidsafe app"),Some((ListedKeywordIndex(3),9)));
+        assert_eq!(keywords.find_keyword_starting("covidious"),None);
+        assert_eq!(keywords.find_keyword_starting("prime minister's office"),Some((ListedKeywordIndex(2),16)));
+        assert_eq!(keywords.find_keyword_starting("prime ministers"),Some((ListedKeywordIndex(1),5)));
+        assert_eq!(keywords.find_keyword_starting("primer"),Some((ListedKeywordIndex(1),6)));
+        assert_eq!(keywords.find_keyword_starting("covid'sy"),Some((ListedKeywordIndex(0),5)));
+        let keywords = keywords_from("boundary_multilingual","Covid,新冠,كورونا\n");
+        assert_eq!(keywords.find_keyword_starting("新冠疫苗"),Some((ListedKeywordIndex(0),6)));
+        assert_eq!(keywords.find_keyword_starting("كورونا جديد"),Some((ListedKeywordIndex(0),12)));
+        #[allow(deprecated)]
+        let single = ListedKeyword(vec!["Prime Minister".to_string(),"ScoMo".to_string()]).find_keyword_starting("scomo's office");
+        assert_eq!(single,Some(7));
+    }
+}
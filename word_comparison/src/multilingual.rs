@@ -0,0 +1,199 @@
+//! Questions in several languages, each tokenized with the general vocabulary for its language.
+//!
+//! The language of each question is guessed (see [detect_language]) and the question tokenized with that language's
+//! vocabulary, or the default (English) vocabulary if there isn't one. Each language gets its own fixed range of word indices
+//! (see [Language::first_word_index]), so that word 5 in the Chinese vocabulary is not mistaken for word 5 in the English one
+//! when indexing and searching, and indices stay the same when a vocabulary is extended or a language added. Words only
+//! match words in the same language; listed keywords with aliases in several languages (see [ListedKeywords]) match across languages.
+
+use std::path::Path;
+use crate::language::{detect_language, Language, WORD_INDICES_PER_LANGUAGE};
+use crate::listed_keywords::ListedKeywords;
+use crate::sentences::{SentencePart, TokenizedSentence};
+use crate::tokenizer::Tokenizer;
+use crate::word::{WordIndex, WordSource};
+use crate::word_file::{WordFileError, WordsInFile, WORD_MMAP_FILE};
+
+/// General vocabularies for languages other than the default one.
+#[derive(Default)]
+pub struct LanguageVocabularies {
+    vocabularies : Vec<(Language,WordsInFile)>,
+}
+
+impl LanguageVocabularies {
+    /// Where the vocabulary for a language is usually found : next to the default one, with the language code added, such as "GeneralVocabulary_vi.bin".
+    pub fn std_location(language:Language) -> String {
+        let (stem,extension) = WORD_MMAP_FILE.rsplit_once('.').unwrap_or((WORD_MMAP_FILE,""));
+        format!("{}_{}.{}",stem,language.code(),extension)
+    }
+
+    /// Load the vocabulary for each language other than English that has a file at its [Self::std_location].
+    pub fn load_available() -> Result<Self,WordFileError> {
+        let mut res = LanguageVocabularies::default();
+        for language in Language::ALL.iter().copied().filter(|&language|language!=Language::English) {
+            let path = Self::std_location(language);
+            if Path::new(&path).exists() { res.add(language,WordsInFile::read_word_file(&path)?); }
+        }
+        Ok(res)
+    }
+
+    /// Use the given vocabulary for questions in the language. It should use the same normalization as the listed keywords.
+    /// English questions always use the default vocabulary given to the [MultilingualTokenizer].
+    pub fn add(&mut self,language:Language,words:WordsInFile) {
+        self.vocabularies.retain(|(existing,_)|*existing!=language);
+        self.vocabularies.push((language,words));
+    }
+
+    pub fn get(&self,language:Language) -> Option<&WordsInFile> {
+        self.vocabularies.iter().find(|(existing,_)|*existing==language).map(|(_,words)|words)
+    }
+
+    pub fn languages(&self) -> impl Iterator<Item=Language> + '_ {
+        self.vocabularies.iter().map(|(language,_)|*language)
+    }
+
+    /// Where the ranges of word indices are usually saved (see [Self::check_index_ranges]).
+    pub const INDEX_RANGES_STD_LOCATION : &'static str = "LanguageIndexRanges.txt";
+
+    /// The range of word indices for each language, one per line : the code, the first index, and the number of indices.
+    pub fn index_ranges() -> String {
+        Language::ALL.iter().map(|language|format!("{} {} {}\n",language.code(),language.first_word_index(),WORD_INDICES_PER_LANGUAGE)).collect()
+    }
+
+    /// Check that word indices stored by an earlier run (such as in a question database) still mean the same words.
+    /// The [Self::index_ranges] are saved to the file the first time, and every range in it must be unchanged after that.
+    /// Each vocabulary, including the default (English) one, must also fit in its range.
+    pub fn check_index_ranges<P:AsRef<Path>>(&self,words:&WordsInFile,path:P) -> std::io::Result<()> {
+        let invalid = |problem:String| std::io::Error::new(std::io::ErrorKind::InvalidData,problem);
+        for (language,words) in std::iter::once((Language::English,words)).chain(self.vocabularies.iter().map(|(language,words)|(*language,words))) {
+            if words.len()>WORD_INDICES_PER_LANGUAGE as usize { return Err(invalid(format!("The {} vocabulary has {} words, more than the {} word indices for a language",language.code(),words.len(),WORD_INDICES_PER_LANGUAGE))); }
+        }
+        let path = path.as_ref();
+        let current = Self::index_ranges();
+        match std::fs::read_to_string(path) {
+            Ok(saved) => match saved.lines().find(|line|!current.lines().any(|range|range==*line)) {
+                Some(changed) => Err(invalid(format!("Word index range {} in {} has changed. Rebuild anything storing word indices and delete the file.",changed,path.display()))),
+                None => Ok(()),
+            }
+            Err(e) if e.kind()==std::io::ErrorKind::NotFound => std::fs::write(path,current),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A [Tokenizer] that tokenizes each question with the vocabulary for its language, using the standard algorithm.
+#[derive(Clone, Copy)]
+pub struct MultilingualTokenizer<'a> {
+    /// The vocabulary for English, and any language without its own.
+    pub words : &'a WordsInFile,
+    pub other_languages : &'a LanguageVocabularies,
+    pub keywords : &'a ListedKeywords,
+}
+
+impl <'a> MultilingualTokenizer<'a> {
+    pub fn new(words:&'a WordsInFile,other_languages:&'a LanguageVocabularies,keywords:&'a ListedKeywords) -> Self { MultilingualTokenizer{words,other_languages,keywords} }
+
+    /// The language and vocabulary of a word index, if it is in one of the other languages rather than the general vocabulary.
+    fn other_language_of(&self,word:WordIndex) -> Option<(Language,&'a WordsInFile)> {
+        let language = Language::of_word_index(word.0).filter(|&language|language!=Language::English)?;
+        Some((language,self.other_languages.get(language)?))
+    }
+}
+
+impl Tokenizer for MultilingualTokenizer<'_> {
+    fn tokenize(&self,text:&str) -> TokenizedSentence {
+        let language = detect_language(text);
+        let (words,offset) = match self.other_languages.get(language) {
+            Some(words) if language!=Language::English => (words,language.first_word_index()),
+            _ => (self.words,0),
+        };
+        let mut tokenized = TokenizedSentence::tokenize(text,words,self.keywords);
+        for part in &mut tokenized.parts {
+            if let SentencePart::Known(word) = part { word.0+=offset; }
+        }
+        tokenized
+    }
+    fn words(&self) -> &WordsInFile { self.words }
+    fn vocabulary(&self,word:WordIndex) -> (&WordsInFile,WordIndex) {
+        match self.other_language_of(word) {
+            Some((_,words)) => (words,WordIndex(word.0%WORD_INDICES_PER_LANGUAGE)),
+            None => (self.words,word),
+        }
+    }
+    fn global_index(&self,word:WordIndex,local:WordIndex) -> WordIndex {
+        match self.other_language_of(word) {
+            Some((language,_)) => WordIndex(language.first_word_index()+local.0),
+            None => local,
+        }
+    }
+    fn keywords(&self) -> &ListedKeywords { self.keywords }
+    fn explain(&self,part:&SentencePart) -> String {
+        match part {
+            SentencePart::Known(word) => {
+                let (words,local) = self.vocabulary(*word);
+                format!("Word {} : {}",word.0,words.word(local))
+            }
+            _ => part.explain(self.words,self.keywords),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comparison_list::{add_question, find_similar_in_database};
+    use crate::flatfile_database_backend::FlatfileDatabaseBackend;
+    use crate::stopwords::StopwordPolicy;
+    use crate::test_util::{keywords_from, small_word_file, temp_path, word_file_from_glove};
+
+    #[test]
+    fn chinese_and_english() {
+        let words = WordsInFile::read_word_file(small_word_file("multilingual")).unwrap();
+        let chinese = word_file_from_glove("multilingual_zh","的 1 0 0\n是 0.9 0.1 0\n疫苗 0 1 0\n接种 0 0 1\n小猫 0.1 0 1\n猫 0.1 0.1 1\n",2);
+        let mut other_languages = LanguageVocabularies::default();
+        other_languages.add(Language::Chinese,WordsInFile::read_word_file(chinese).unwrap());
+        let keywords = keywords_from("multilingual","Covid,新冠\n");
+        let tokenizer = MultilingualTokenizer::new(&words,&other_languages,&keywords);
+        let text = "新冠疫苗接种的小猫X";
+        let tokenized = tokenizer.tokenize(text);
+        let explained : Vec<String> = tokenized.parts.iter().zip(&tokenized.spans).map(|(part,span)|format!("{} {}",tokenizer.explain(part),&text[span.clone()])).collect();
+        let zh = Language::Chinese.first_word_index();
+        assert_eq!(explained,vec!["Keyword 0 : Covid 新冠".to_string(),format!("Word {} : 疫苗 疫苗",zh+2),format!("Word {} : 接种 接种",zh+3),format!("Word {} : 的 的",zh),format!("Word {} : 小猫 小猫",zh+4),"Unknown x X".to_string()]);
+        let (_,local) = tokenizer.vocabulary(WordIndex(zh+3));
+        assert_eq!((local,tokenizer.global_index(WordIndex(zh+3),local)),(WordIndex(3),WordIndex(zh+3)));
+        assert_eq!(tokenizer.global_index(WordIndex(3),local),WordIndex(3)); // the same index in the general vocabulary.
+        let stopwords = StopwordPolicy::Rank(2);
+        let database = temp_path("multilingual_database.txt");
+        let _ = std::fs::remove_file(&database);
+        let mut backend : FlatfileDatabaseBackend<u32> = FlatfileDatabaseBackend::new(&database,&tokenizer,&stopwords).unwrap();
+        add_question(&mut backend,"Is the covid vaccine safe for my cat?",1,&tokenizer,&stopwords).unwrap();
+        add_question(&mut backend,"新冠疫苗接种",2,&tokenizer,&stopwords).unwrap();
+        add_question(&mut backend,"小猫接种",3,&tokenizer,&stopwords).unwrap();
+        let found = find_similar_in_database(&mut backend,"新冠疫苗",&tokenizer,&stopwords).unwrap();
+        assert_eq!(found.iter().map(|f|f.id).collect::<Vec<_>>(),vec![2,1]); // the English question only via the keyword.
+    }
+
+    #[test]
+    fn empty_vocabulary_and_stable_ranges() {
+        let words = WordsInFile::read_word_file(small_word_file("multilingual_empty")).unwrap();
+        let vietnamese = word_file_from_glove("multilingual_empty_vi","",2);
+        let arabic = word_file_from_glove("multilingual_empty_ar","لقاح 1 0 0\nقطة 0 1 0\n",1);
+        let mut other_languages = LanguageVocabularies::default();
+        other_languages.add(Language::Vietnamese,WordsInFile::read_word_file(vietnamese).unwrap());
+        other_languages.add(Language::Arabic,WordsInFile::read_word_file(arabic).unwrap());
+        let keywords = keywords_from("multilingual_empty","");
+        let tokenizer = MultilingualTokenizer::new(&words,&other_languages,&keywords);
+        let vietnamese = tokenizer.tokenize("Tại sao chậm");
+        assert!(vietnamese.parts.iter().all(|part|matches!(part,SentencePart::Unknown(_))));
+        let arabic = tokenizer.tokenize("قطة");
+        assert!(matches!(arabic.parts[..],[SentencePart::Known(WordIndex(word))] if word==Language::Arabic.first_word_index()+1));
+        assert_eq!(tokenizer.explain(&arabic.parts[0]),format!("Word {} : قطة",Language::Arabic.first_word_index()+1));
+        assert!(matches!(tokenizer.tokenize("cat").parts[..],[SentencePart::Known(WordIndex(3))]));
+        let ranges = temp_path("multilingual_index_ranges.txt");
+        let _ = std::fs::remove_file(&ranges);
+        other_languages.check_index_ranges(&words,&ranges).unwrap(); // saves them
+        other_languages.check_index_ranges(&words,&ranges).unwrap();
+        std::fs::write(&ranges,"zh 5 10\n").unwrap();
+        assert!(other_languages.check_index_ranges(&words,&ranges).is_err());
+    }
+}
@@ -20,6 +20,7 @@ impl Ord for WordAndValue {
         }
     }
 }
+#[allow(clippy::non_canonical_partial_ord_impl)] // None for NaN values, rather than panicking as cmp does.
 impl PartialOrd for WordAndValue {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         self.value.partial_cmp(&other.value)
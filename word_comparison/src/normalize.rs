@@ -0,0 +1,133 @@
+//! Make text that looks the same to a person into the same string, so that it gives the same tokens.
+//!
+//! Text is converted to Unicode normalization form NFKC (so e.g. full width letters and ligatures become
+//! ordinary letters, and non-breaking spaces become spaces), curly quotes and the many different dashes
+//! are folded to their ASCII equivalents, invisible characters like zero width spaces (and the Arabic tatweel used to stretch words) are removed, and it is
+//! converted to lower case. Optionally accents are removed too, so "Duttón" is the same as "Dutton".
+//!
+//! The same normalization must be used when building the vocabulary, loading listed keywords, and tokenizing,
+//! so the vocabulary file records the normalization used (see [WordsInFile::normalization](crate::word_file::WordsInFile::normalization)).
+
+use std::ops::Range;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// Remove accents and other combining marks, so "é" becomes "e".
+    pub strip_accents : bool,
+}
+
+/// Replace typographic quotes and dashes by ASCII ones. None means remove the character.
+fn fold(c:char) -> Option<char> {
+    match c {
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' | '\u{02BC}' => Some('\''),
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' | '\u{00AB}' | '\u{00BB}' => Some('"'),
+        '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{FE58}' | '\u{FE63}' => Some('-'),
+        '\u{00AD}' | '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' => None, // soft hyphen and zero width characters.
+        '\u{0640}' => None, // Arabic tatweel, which only stretches a word for layout.
+        c => Some(c),
+    }
+}
+
+impl Normalization {
+    /// Bits stored in the word file.
+    pub(crate) fn to_flags(self) -> u32 { if self.strip_accents { 1 } else { 0 } }
+    pub(crate) fn from_flags(flags:u32) -> Self { Normalization{ strip_accents: flags&1!=0 } }
+
+    /// Normalize the text, as described in the [module documentation](self).
+    pub fn normalize(&self,text:&str) -> String {
+        let folded : String = text.nfkc().filter_map(fold).flat_map(char::to_lowercase).collect();
+        if self.strip_accents { folded.nfd().filter(|c|!is_combining_mark(*c)).nfc().collect() } else { folded }
+    }
+
+    /// Normalize the text, and also change runs of whitespace to a single space and remove leading and trailing whitespace,
+    /// keeping track of where each byte of the result came from so tokens can be mapped back to the original text.
+    ///
+    /// A character and any following combining marks are normalized together, separately from the rest of the text, so
+    /// the position of every normalized character is known. This gives the same result as [Self::normalize] except in
+    /// unusual cases such as Hangul written as separate jamo.
+    pub fn normalize_tracking_offsets(&self,text:&str) -> NormalizedText {
+        let mut res = NormalizedText{ text: String::new(), sources: vec![] };
+        let mut pending_space : Option<Range<usize>> = None;
+        let mut add = |res:&mut NormalizedText,cluster:Range<usize>| {
+            for c in self.normalize(&text[cluster.clone()]).chars() {
+                if c.is_whitespace() {
+                    if !res.text.is_empty() && pending_space.is_none() { pending_space=Some(cluster.clone()); }
+                } else {
+                    if let Some(space) = pending_space.take() { res.text.push(' '); res.sources.push(space); }
+                    res.text.push(c);
+                    for _ in 0..c.len_utf8() { res.sources.push(cluster.clone()); }
+                }
+            }
+        };
+        let mut cluster_start = 0;
+        for (pos,c) in text.char_indices() {
+            if pos>0 && !is_combining_mark(c) {
+                add(&mut res,cluster_start..pos);
+                cluster_start=pos;
+            }
+        }
+        if !text.is_empty() { add(&mut res,cluster_start..text.len()); }
+        res
+    }
+}
+
+/// Normalized text that remembers where each part came from in the original text.
+#[derive(Clone, Debug)]
+pub struct NormalizedText {
+    pub text : String,
+    /// For each byte of text, the range of bytes in the original text it came from.
+    sources : Vec<Range<usize>>,
+}
+
+impl NormalizedText {
+    /// The bytes of the original text that the given (non empty) range of bytes in the normalized text came from.
+    pub fn original_span(&self,range:Range<usize>) -> Range<usize> {
+        self.sources[range.start].start..self.sources[range.end-1].end
+    }
+
+    /// The start of the bytes of the original text that the byte at pos in the normalized text came from.
+    pub fn original_start(&self,pos:usize) -> usize { self.sources[pos].start }
+
+    pub(crate) fn empty() -> Self { NormalizedText{ text: String::new(), sources: vec![] } }
+
+    /// Append the given range of another normalized text, remembering where it came from.
+    pub(crate) fn push_from(&mut self,other:&NormalizedText,range:Range<usize>) {
+        self.text.push_str(&other.text[range.clone()]);
+        self.sources.extend_from_slice(&other.sources[range]);
+    }
+
+    /// Append new text, which came from the given range of the original text.
+    pub(crate) fn push_with_source(&mut self,s:&str,source:Range<usize>) {
+        self.text.push_str(s);
+        self.sources.extend(std::iter::repeat_n(source,s.len()));
+    }
+
+    /// Make the last byte of the text also cover the original text up to end, for text that was removed.
+    pub(crate) fn extend_last_source(&mut self,end:usize) {
+        if let Some(last) = self.sources.last_mut() { last.end=last.end.max(end); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equivalent_text_is_equal() {
+        let normalization = Normalization::default();
+        assert_eq!(normalization.normalize("Morrison\u{2019}s COVID\u{2013}19 \u{FF21}\u{00A0}plan\u{200B}"),"morrison's covid-19 a plan");
+        assert_eq!(normalization.normalize("Dutt\u{00F3}n"),"dutt\u{00F3}n");
+        assert_eq!(normalization.normalize("Dutto\u{0301}n"),"dutt\u{00F3}n"); // decomposed accent
+        let strip = Normalization{ strip_accents: true };
+        assert_eq!(strip.normalize("Dutt\u{00F3}n"),"dutton");
+        assert_eq!(Normalization::from_flags(strip.to_flags()),strip);
+        let original = "  \u{FF21}  Dutto\u{0301}n\u{200B}\u{2019}s ";
+        let tracked = strip.normalize_tracking_offsets(original);
+        assert_eq!(tracked.text,"a dutton's");
+        assert_eq!(&original[tracked.original_span(0..1)],"\u{FF21}");
+        assert_eq!(&original[tracked.original_span(2..8)],"Dutto\u{0301}n");
+        assert_eq!(&original[tracked.original_span(2..10)],"Dutto\u{0301}n\u{200B}\u{2019}s");
+    }
+}
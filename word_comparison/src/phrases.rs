@@ -0,0 +1,135 @@
+//! Multi-word phrases such as "climate change" or "new south wales" in the general vocabulary.
+//!
+//! The words making up a phrase are often common, so they score poorly (or are not indexed at all),
+//! while the phrase itself is quite specific. Phrases are added to the vocabulary as words containing single spaces,
+//! after the ordinary words, with a vector being the mean of the vectors of their component words. They then get
+//! synonyms like any other word, and the tokenizer matches the longest phrase before falling back to single words.
+//!
+//! Phrases can come from a hand made list, or be detected as collocations in a corpus.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+use crate::normalize::Normalization;
+use crate::word::{MemoryWords, WordSource, WordVec, WordVecs};
+
+/// Convert to the form phrases are stored in : normalized in the same way as the vocabulary, words separated by single spaces.
+pub fn normalize_phrase(phrase:&str,normalization:Normalization) -> String {
+    normalization.normalize(phrase).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Read a list of phrases, one per line. Blank lines and lines starting with # are ignored.
+/// The phrases are as written, and are normalized by [add_phrases].
+pub fn read_phrase_list<P:AsRef<Path>>(path:P) -> std::io::Result<Vec<String>> {
+    let mut res = vec![];
+    for line in std::io::BufReader::new(std::fs::File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') { res.push(line.to_string()); }
+    }
+    Ok(res)
+}
+
+/// Choices for detecting collocations.
+#[derive(Clone, Debug)]
+pub struct CollocationOptions {
+    /// Ignore pairs of words that occur together fewer times than this.
+    pub min_count : u64,
+    /// Minimum score for a pair to be a collocation. See [detect_collocations].
+    pub threshold : f64,
+    /// The maximum number of collocations to return.
+    pub max_phrases : usize,
+}
+
+impl Default for CollocationOptions {
+    fn default() -> Self { CollocationOptions{ min_count: 5, threshold: 100.0, max_phrases: 10000 } }
+}
+
+/// Find pairs of words that occur together in the text file more often than chance would suggest, best first.
+///
+/// Uses the word2vec phrase score `(count(a b)-min_count)*total_words/(count(a)*count(b))`. Words are split on
+/// whitespace with surrounding punctuation removed, and pairs are not counted across punctuation, so "end. The" is not a pair.
+pub fn detect_collocations<P:AsRef<Path>>(corpus:P,options:&CollocationOptions) -> std::io::Result<Vec<(String,f64)>> {
+    let mut unigrams : HashMap<String,u64> = HashMap::new();
+    let mut bigrams : HashMap<(String,String),u64> = HashMap::new();
+    let mut total_words = 0;
+    for line in std::io::BufReader::new(std::fs::File::open(corpus)?).lines() {
+        let line = line?.to_lowercase();
+        let mut previous : Option<&str> = None;
+        for token in line.split_whitespace() {
+            let word = token.trim_matches(|c:char|!c.is_alphanumeric());
+            if word.is_empty() { previous=None; continue; }
+            total_words+=1;
+            *unigrams.entry(word.to_string()).or_default()+=1;
+            if let Some(previous) = previous {
+                if !token.starts_with(|c:char|!c.is_alphanumeric()) { *bigrams.entry((previous.to_string(),word.to_string())).or_default()+=1; }
+            }
+            previous = if token.ends_with(|c:char|!c.is_alphanumeric()) { None } else { Some(word) };
+        }
+    }
+    let mut res : Vec<(String,f64)> = bigrams.into_iter().filter(|(_,count)|*count>=options.min_count).map(|((a,b),count)|{
+        let score = (count-options.min_count) as f64*total_words as f64/(unigrams[&a] as f64*unigrams[&b] as f64);
+        (format!("{} {}",a,b),score)
+    }).filter(|(_,score)|*score>=options.threshold).collect();
+    res.sort_by(|a,b|b.1.total_cmp(&a.1).then_with(||a.0.cmp(&b.0)));
+    res.truncate(options.max_phrases);
+    Ok(res)
+}
+
+/// The result of adding phrases to a vocabulary.
+#[derive(Debug, Default)]
+pub struct PhraseReport {
+    /// Number of phrases added.
+    pub added : usize,
+    /// Phrases that were already in the vocabulary, or were not phrases (a single word).
+    pub already_present : Vec<String>,
+    /// Phrases with a component word not in the vocabulary, which therefore have no vector.
+    pub unknown_components : Vec<String>,
+}
+
+/// Add the phrases to the end of the words, each with the mean of its component words' vectors.
+/// The phrases are normalized first, which should be the same normalization as the words.
+pub fn add_phrases(words:&mut MemoryWords,wordvecs:&mut WordVecs,phrases:&[String],normalization:Normalization) -> PhraseReport {
+    let mut report = PhraseReport::default();
+    for phrase in phrases {
+        let phrase = normalize_phrase(phrase,normalization);
+        if !phrase.contains(' ') || words.index(&phrase).is_some() { report.already_present.push(phrase); continue; }
+        let components : Option<Vec<&WordVec>> = phrase.split(' ').map(|w|words.index(w).map(|i|wordvecs.get(i))).collect();
+        let components = match components { Some(components) => components, None => { report.unknown_components.push(phrase); continue; } };
+        let mut sum = vec![0.0;components[0].values().len()];
+        for component in &components {
+            for (total,v) in sum.iter_mut().zip(component.values()) { *total+=v; }
+        }
+        let mean = WordVec::new(sum.into_iter().map(|v|v/components.len() as f64).collect());
+        words.add(&phrase);
+        wordvecs.push(mean);
+        report.added+=1;
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word::WordIndex;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn collocations_and_phrases() {
+        let corpus = temp_path("corpus.txt");
+        let mut text = String::new();
+        for i in 0..20 { text.push_str(&format!("the climate change debate {} continues. Climate is weather, change is constant.\n",i)); }
+        std::fs::write(&corpus,text).unwrap();
+        let found = detect_collocations(&corpus,&CollocationOptions{ min_count:5, threshold:1.0, max_phrases:10 }).unwrap();
+        assert!(found.iter().any(|(phrase,_)|phrase=="climate change"));
+        assert!(!found.iter().any(|(phrase,_)|phrase=="continues climate")); // across a full stop.
+        let mut words = MemoryWords::default();
+        let mut wordvecs = WordVecs::default();
+        for (word,vec) in [("climate",vec![1.0,0.0]),("change",vec![0.0,1.0])] { words.add(word); wordvecs.push(WordVec::new(vec)); }
+        let report = add_phrases(&mut words,&mut wordvecs,&["Climate\u{00A0} Change".to_string(),"climate".to_string(),"climate crisis".to_string()],Normalization::default());
+        assert_eq!(report.added,1);
+        assert_eq!(words.index("climate change"),Some(WordIndex(2)));
+        assert_eq!(wordvecs.get(WordIndex(2)).values(),&[0.5,0.5]);
+        assert_eq!(report.unknown_components,vec!["climate crisis".to_string()]);
+    }
+}
@@ -0,0 +1,155 @@
+//! A general vocabulary that can be replaced while the program is running.
+//!
+//! The vocabulary is read into memory (not memory mapped) so the file can safely be modified.
+//! Users get an `Arc` to the current copy, so something in the middle of a query keeps using
+//! the old copy until it is finished, even if a new one is swapped in.
+//!
+//! Note that [WordIndex](crate::word::WordIndex) values from one vocabulary mean nothing in a different one,
+//! so anything indexed by them (such as a question database) needs to be rebuilt when the vocabulary is replaced.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+use crate::word_file::{WordFileError, WordsInFile};
+use crate::synonym_overlay::SynonymOverlay;
+use crate::lemmas::Lemmas;
+
+/// Enough information about a file to tell whether it has been modified or replaced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileFingerprint {
+    length : u64,
+    modified : Option<SystemTime>,
+    /// device and inode, which change if the file is replaced by a rename.
+    #[cfg(unix)]
+    inode : (u64,u64),
+}
+
+impl FileFingerprint {
+    fn of(path:&Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(FileFingerprint{
+            length: metadata.len(),
+            modified: metadata.modified().ok(),
+            #[cfg(unix)]
+            inode: { use std::os::unix::fs::MetadataExt; (metadata.dev(),metadata.ino()) },
+        })
+    }
+}
+
+/// Read the file and apply the overlays, also returning the synonym overlay words that are not in the file.
+fn read(path:&Path,synonym_overlay:&Option<SynonymOverlay>,lemmas:&Option<Lemmas>) -> Result<(WordsInFile,Vec<String>),WordFileError> {
+    let mut words = WordsInFile::read_word_file_into_memory(path)?;
+    if let Some(lemmas) = lemmas { words.apply_lemmas(lemmas); }
+    let unknown = match synonym_overlay {
+        Some(overlay) => words.apply_synonym_overlay(overlay),
+        None => vec![],
+    };
+    Ok((words,unknown))
+}
+
+pub struct ReloadableWordsInFile {
+    path : PathBuf,
+    current : RwLock<Arc<WordsInFile>>,
+    /// The fingerprint of the file the last time it was loaded (or a load was attempted).
+    fingerprint : Mutex<Option<FileFingerprint>>,
+    /// Applied to each version of the file as it is loaded.
+    synonym_overlay : Option<SynonymOverlay>,
+    /// Applied to each version of the file as it is loaded, replacing any lemmas in the file.
+    lemmas : Option<Lemmas>,
+}
+
+impl ReloadableWordsInFile {
+    /// Load the word file into memory.
+    pub fn load<P:AsRef<Path>>(path:P) -> Result<Self,WordFileError> {
+        Ok(Self::load_with_synonym_overlay(path,None)?.0)
+    }
+
+    /// Load the word file into memory, applying the synonym overlay to it (and to any later versions).
+    /// Also returns the words in the overlay that are not in the file.
+    pub fn load_with_synonym_overlay<P:AsRef<Path>>(path:P,synonym_overlay:Option<SynonymOverlay>) -> Result<(Self,Vec<String>),WordFileError> {
+        Self::load_with_overlays(path,synonym_overlay,None)
+    }
+
+    /// Load the word file into memory, applying the synonym overlay and lemmas to it (and to any later versions).
+    /// Also returns the words in the overlay that are not in the file.
+    pub fn load_with_overlays<P:AsRef<Path>>(path:P,synonym_overlay:Option<SynonymOverlay>,lemmas:Option<Lemmas>) -> Result<(Self,Vec<String>),WordFileError> {
+        let path = path.as_ref().to_path_buf();
+        let fingerprint = FileFingerprint::of(&path).ok();
+        let (words,unknown) = read(&path,&synonym_overlay,&lemmas)?;
+        Ok((ReloadableWordsInFile{ path, current: RwLock::new(Arc::new(words)), fingerprint: Mutex::new(fingerprint), synonym_overlay, lemmas },unknown))
+    }
+
+    /// The vocabulary currently in use. This will not change underneath the caller even if a new one is swapped in.
+    pub fn current(&self) -> Arc<WordsInFile> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// If the file has been modified or replaced since it was last loaded, load the new one and return it (without swapping it in),
+    /// along with the words in the synonym overlay that are not in it.
+    ///
+    /// A file that is still being written will usually fail to load (e.g. checksum mismatch). In this case the error is returned,
+    /// and the load will be attempted again next time if the file changes again.
+    pub fn check_for_new_version(&self) -> Result<Option<(WordsInFile,Vec<String>)>,WordFileError> {
+        let fingerprint = FileFingerprint::of(&self.path)?;
+        let mut last = self.fingerprint.lock().unwrap();
+        if last.as_ref()==Some(&fingerprint) { return Ok(None); }
+        *last = Some(fingerprint);
+        read(&self.path,&self.synonym_overlay,&self.lemmas).map(Some)
+    }
+
+    /// Swap in a new vocabulary, returning the old one. Anyone still using the old one keeps using it until they drop it.
+    pub fn replace<W:Into<Arc<WordsInFile>>>(&self,words:W) -> Arc<WordsInFile> {
+        std::mem::replace(&mut *self.current.write().unwrap(),words.into())
+    }
+
+    /// Combine [Self::check_for_new_version] and [Self::replace], returning true if a new version was swapped in.
+    /// Synonym overlay words that are not in the new version are ignored.
+    pub fn reload_if_changed(&self) -> Result<bool,WordFileError> {
+        match self.check_for_new_version()? {
+            Some((words,_)) => { self.replace(words); Ok(true) }
+            None => Ok(false),
+        }
+    }
+
+    pub fn path(&self) -> &Path { &self.path }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word::{WordIndex, WordSource};
+    use crate::test_util::{small_word_file, temp_path};
+
+    #[test]
+    fn replaced_file_is_swapped_in() {
+        let path = temp_path("reloadable.bin");
+        std::fs::copy(small_word_file("reloadable_source"),&path).unwrap();
+        let reloadable = ReloadableWordsInFile::load(&path).unwrap();
+        let old = reloadable.current();
+        assert!(!reloadable.reload_if_changed().unwrap());
+        // replace the file by renaming a different one over it.
+        let mut legacy = b"WORD".to_vec();
+        for v in [1u32,0,0,0,2] { legacy.extend_from_slice(&v.to_le_bytes()); }
+        legacy.extend_from_slice(b"a\0");
+        let new_path = temp_path("reloadable.bin.new");
+        std::fs::write(&new_path,&legacy).unwrap();
+        std::fs::rename(&new_path,&path).unwrap();
+        assert!(reloadable.reload_if_changed().unwrap());
+        assert_eq!(reloadable.current().len(),1);
+        assert_eq!(old.word(WordIndex(3)),"cat");
+    }
+
+    #[test]
+    fn unknown_overlay_words_returned() {
+        let path = temp_path("reloadable_overlay.bin");
+        std::fs::copy(small_word_file("reloadable_overlay_source"),&path).unwrap();
+        let overlay_path = temp_path("reloadable_overlay.csv");
+        std::fs::write(&overlay_path,"block,kitten\nblock,unknownword\n").unwrap();
+        let (reloadable,unknown) = ReloadableWordsInFile::load_with_synonym_overlay(&path,Some(SynonymOverlay::load(&overlay_path).unwrap())).unwrap();
+        assert_eq!(unknown,vec!["unknownword".to_string()]);
+        assert!(reloadable.check_for_new_version().unwrap().is_none());
+        std::fs::rename(small_word_file("reloadable_overlay_source2"),&path).unwrap(); // a new inode, so seen as a new version.
+        let (_,unknown) = reloadable.check_for_new_version().unwrap().unwrap();
+        assert_eq!(unknown,vec!["unknownword".to_string()]);
+    }
+}
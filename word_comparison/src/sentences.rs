@@ -1,13 +1,26 @@
 //! Code to deal with sentences rather than words.
 
 use crate::listed_keywords::{ListedKeywordIndex, ListedKeywords};
-use crate::word::{WordIndex, WordSource};
+use crate::word::{WordIndex, WordSource, WordVec};
 use crate::word_file::WordsInFile;
+use crate::spelling::SpellingCorrector;
+use crate::tokenizer::Tokenizer;
+use crate::normalize::NormalizedText;
+use crate::contractions::normalize_for_tokenizing;
+use crate::typed_tokens::{parse_typed, TypedValue};
+use crate::language::is_unspaced;
+use std::ops::Range;
 
 pub enum SentencePart {
     Listed(ListedKeywordIndex),
     Known(WordIndex),
     Unknown(String),
+    /// A hashtag such as "#robodebt", without the "#". The words it is made of are also tokens.
+    Hashtag(String),
+    /// A mention of an account such as "@scomo", without the "@".
+    Mention(String),
+    /// A number, amount of money, date or the like, matched by value.
+    Typed(TypedValue),
 }
 
 impl SentencePart {
@@ -16,21 +29,41 @@ impl SentencePart {
             SentencePart::Listed(keyword) => format!("Keyword {} : {}",keyword.0,keywords.canonical(*keyword)),
             SentencePart::Known(word) => format!("Word {} : {}",word.0,words.word(*word)),
             SentencePart::Unknown(token) => format!("Unknown {}",token),
+            SentencePart::Hashtag(tag) => format!("Hashtag #{}",tag),
+            SentencePart::Mention(account) => format!("Mention @{}",account),
+            SentencePart::Typed(value) => value.to_string(),
         }
     }
 }
 
 pub struct TokenizedSentence {
     pub parts : Vec<SentencePart>,
+    /// For each of the parts, the range of bytes in the original text that it came from.
+    pub spans : Vec<Range<usize>>,
+    /// The parts that are spelling corrections of an unknown word. See [Self::correct_spelling].
+    pub corrections : Vec<Correction>,
+}
+
+/// A part of a tokenized sentence that is a correction of the spelling of an unknown word.
+#[derive(Clone, Debug)]
+pub struct Correction {
+    /// The index of the corrected word in [TokenizedSentence::parts].
+    pub part : usize,
+    /// The unknown word that was corrected.
+    pub original : String,
+    /// The edit distance from the original to the correction.
+    pub distance : usize,
+    /// The score for a match via the correction is multiplied by this.
+    pub penalty : f64,
 }
 
 /// Get the length of the next token. 0 if starts with whitespace.
-/// look for something terminated by whitespace, and remove trailing punctuation.
+/// look for something terminated by whitespace (or the start of text in a script without spaces), and remove trailing punctuation.
 fn len_next_token(s:&str) -> usize {
     let mut last_was_not_punctuation = false;
     let mut last_start_punctuation = 0;
     for (pos,c) in s.char_indices() {
-        if c.is_whitespace() {
+        if c.is_whitespace() || (pos>0 && is_unspaced(c)) {
             return if last_was_not_punctuation || last_start_punctuation==0 { pos } else { last_start_punctuation }
         } else if c.is_alphanumeric() {
             last_was_not_punctuation=true;
@@ -44,15 +77,145 @@ fn len_next_token(s:&str) -> usize {
     if last_was_not_punctuation || last_start_punctuation==0 { s.len() } else { last_start_punctuation }
 }
 
+/// If s starts with a hashtag or mention, such as "#robodebt" or "@scomo", its length.
+fn len_hashtag_or_mention(s:&str) -> Option<usize> {
+    if !(s.starts_with('#') || s.starts_with('@')) { return None; }
+    let len = s[1..].find(|c:char|!(c.is_alphanumeric() || c=='_')).unwrap_or(s.len()-1);
+    if s[1..1+len].chars().any(char::is_alphanumeric) { Some(1+len) } else { None }
+}
+
+/// The token for a piece of a hashtag, which should be a whole word, keyword or phrase.
+fn whole_token(piece:&str,words:&WordsInFile,keywords:&ListedKeywords) -> Option<SentencePart> {
+    if let Some(keyword) = keywords.keyword(piece) { Some(SentencePart::Listed(keyword)) }
+    else { words.index(piece).map(SentencePart::Known) }
+}
+
+/// The longest piece of a hashtag that dictionary segmentation will consider as a single word, in bytes.
+const MAX_SEGMENT_LEN : usize = 30;
+
+/// The cost in [dictionary_segmentation] of a single character that is not a word, when that is allowed.
+/// More than a word, so a two character word is preferred to two separate characters.
+const UNKNOWN_CHARACTER_COST : f64 = 1.5;
+
+/// Split text into the fewest vocabulary words or keywords, preferring more common words. None if that is not possible.
+/// If unknown_characters is true, single characters that are not words are allowed (as unknown words), so it is always possible.
+/// This is the usual dynamic programming algorithm, best[i] being the best way of splitting text[..i].
+fn dictionary_segmentation(text:&str,words:&WordsInFile,keywords:&ListedKeywords,unknown_characters:bool) -> Option<Vec<(SentencePart,Range<usize>)>> {
+    let mut best : Vec<Option<(f64,usize)>> = vec![None;text.len()+1]; // cost, and the start of the last piece.
+    best[0]=Some((0.0,0));
+    for end in 1..=text.len() {
+        if !text.is_char_boundary(end) { continue; }
+        for start in end.saturating_sub(MAX_SEGMENT_LEN)..end {
+            if !text.is_char_boundary(start) { continue; }
+            if let Some((cost_before,_)) = best[start] {
+                let piece_cost = match whole_token(&text[start..end],words,keywords) {
+                    Some(SentencePart::Listed(_)) => 1.0,
+                    Some(SentencePart::Known(word)) if text[start..end].chars().all(char::is_alphanumeric) => 1.0+((word.0+1) as f64).ln()/100.0, // favour common words.
+                    _ if unknown_characters && text[start..end].chars().count()==1 => UNKNOWN_CHARACTER_COST,
+                    _ => continue,
+                };
+                if best[end].map(|(cost,_)|cost_before+piece_cost<cost).unwrap_or(true) { best[end]=Some((cost_before+piece_cost,start)); }
+            }
+        }
+    }
+    best[text.len()]?;
+    let mut res = vec![];
+    let mut end = text.len();
+    while end>0 {
+        let start = best[end].unwrap().1;
+        let piece = &text[start..end];
+        res.push((whole_token(piece,words,keywords).unwrap_or_else(||SentencePart::Unknown(piece.to_string())),start..end));
+        end=start;
+    }
+    res.reverse();
+    Some(res)
+}
+
+/// Find the words making up the body of a hashtag, at the given range in the normalized text, with their ranges.
+///
+/// The body is split at underscores, changes between letters and digits, and camel case in the original text
+/// (so "#RoboDebt" is "robo" and "debt"). Each piece that is not itself a word or keyword is split into the
+/// fewest words or keywords possible (so "#robodebt" is also "robo" and "debt"), and if that is not possible is an unknown word.
+/// The whole body is used if it is a word or keyword (so "#covid19" can be a keyword).
+pub fn segment_hashtag(original:&str,normalized:&NormalizedText,body:Range<usize>,words:&WordsInFile,keywords:&ListedKeywords) -> Vec<(SentencePart,Range<usize>)> {
+    if let Some(part) = whole_token(&normalized.text[body.clone()],words,keywords) { return vec![(part,body)]; }
+    let original_char = |pos:usize| original[normalized.original_start(pos)..].chars().next().unwrap_or(' ');
+    let mut pieces : Vec<Range<usize>> = vec![];
+    let mut piece_start = body.start;
+    let mut previous : Option<char> = None;
+    let chars : Vec<(usize,char)> = normalized.text[body.clone()].char_indices().map(|(pos,c)|(body.start+pos,c)).collect();
+    for (i,&(pos,c)) in chars.iter().enumerate() {
+        if c=='_' {
+            if pos>piece_start { pieces.push(piece_start..pos); }
+            piece_start=pos+1;
+            previous=None;
+            continue;
+        }
+        let here = original_char(pos);
+        if let Some(before) = previous {
+            let next_is_lower = chars.get(i+1).map(|&(next,_)|original_char(next).is_lowercase()).unwrap_or(false);
+            let boundary = (before.is_lowercase() && here.is_uppercase()) || (before.is_uppercase() && here.is_uppercase() && next_is_lower) || (before.is_numeric()!=here.is_numeric());
+            if boundary && pos>piece_start {
+                pieces.push(piece_start..pos);
+                piece_start=pos;
+            }
+        }
+        previous=Some(here);
+    }
+    if body.end>piece_start { pieces.push(piece_start..body.end); }
+    let mut res = vec![];
+    for piece in pieces {
+        let text = &normalized.text[piece.clone()];
+        if let Some(part) = whole_token(text,words,keywords) { res.push((part,piece)); }
+        else if let Some(segmented) = dictionary_segmentation(text,words,keywords,false) {
+            res.extend(segmented.into_iter().map(|(part,range)|(part,piece.start+range.start..piece.start+range.end)));
+        } else { res.push((SentencePart::Unknown(text.to_string()),piece)); }
+    }
+    res
+}
+
 impl TokenizedSentence {
+    /// Split the text into tokens. Listed keywords take priority over general vocabulary, but if a listed keyword
+    /// is also a general vocabulary word covering the same text, both are produced as alternatives, with the same span.
+    /// Hashtags are followed by the words they are made of (see [segment_hashtag]), each with its own span.
+    /// Text in a script written without spaces, such as Chinese, is split into the fewest vocabulary words and keywords,
+    /// with characters that are not part of any word as unknown words.
     pub fn tokenize(text:&str, words:&WordsInFile, keywords:&ListedKeywords) -> Self {
         let mut parts = vec![];
-        let lower_case = text.to_lowercase();
-        let mut left = lower_case.trim();
+        let mut spans = vec![];
+        let normalized = normalize_for_tokenizing(words.normalization(),text); // single spaces between words, so multi-word phrases in the vocabulary match.
+        let mut left = normalized.text.as_str();
         while !left.is_empty() {
-            let used = if let Some((keyword,used))=keywords.find_keyword_starting(left) {
+            let start = normalized.text.len()-left.len();
+            let mut extra : Vec<(SentencePart,Range<usize>)> = vec![]; // tokens after the main one, with ranges in the normalized text.
+            let used = if let Some(used) = len_hashtag_or_mention(left) {
+                let tag = left[1..used].to_string();
+                if left.starts_with('#') {
+                    extra = segment_hashtag(text,&normalized,start+1..start+used,words,keywords);
+                    parts.push(SentencePart::Hashtag(tag));
+                } else {
+                    parts.push(SentencePart::Mention(tag));
+                }
+                used
+            } else if let Some((value,used)) = parse_typed(left) {
+                parts.push(SentencePart::Typed(value));
+                used
+            } else if let Some((keyword,used))=keywords.find_keyword_starting(left) {
                 parts.push(SentencePart::Listed(keyword));
+                if let Some((word,word_used)) = words.index_starting(left) {
+                    if word_used==used { // also a general vocabulary word, so its synonyms can be used too.
+                        extra.push((SentencePart::Known(word),start..start+used));
+                    }
+                }
                 used
+            } else if left.starts_with(is_unspaced) {
+                let run = left.find(|c:char|!is_unspaced(c)).unwrap_or(left.len());
+                for (part,range) in dictionary_segmentation(&left[..run],words,keywords,true).unwrap_or_default() {
+                    parts.push(part);
+                    spans.push(normalized.original_span(start+range.start..start+range.end));
+                }
+                left=left[run..].trim_start();
+                continue;
             } else if let Some((keyword,used))=words.index_starting(left) {
                 parts.push(SentencePart::Known(keyword));
                 used
@@ -61,14 +224,65 @@ impl TokenizedSentence {
                 parts.push(SentencePart::Unknown(left[..len].to_string()));
                 len
             };
+            spans.push(normalized.original_span(start..start+used));
+            for (part,range) in extra {
+                parts.push(part);
+                spans.push(normalized.original_span(range));
+            }
             left=left[used..].trim_start();
         }
-        TokenizedSentence {parts}
+        TokenizedSentence {parts,spans,corrections:vec![]}
+    }
+
+    /// Add the closest correction of each unknown word that can be corrected as an alternative for the same text.
+    pub fn correct_spelling(&mut self,corrector:&SpellingCorrector) {
+        let mut parts = vec![];
+        let mut spans = vec![];
+        for (part,span) in std::mem::take(&mut self.parts).into_iter().zip(std::mem::take(&mut self.spans)) {
+            let correction = if let SentencePart::Unknown(word) = &part { corrector.correct(word).map(|(corrected,distance)|(corrected,Correction{ part: parts.len()+1, original: word.clone(), distance, penalty: corrector.penalty() })) } else { None };
+            parts.push(part);
+            spans.push(span.clone());
+            if let Some((corrected,correction)) = correction {
+                parts.push(corrected);
+                spans.push(span);
+                self.corrections.push(correction);
+            }
+        }
+        self.parts=parts;
+        self.spans=spans;
     }
 
-    pub fn explain(&self,words:&WordsInFile,keywords:&ListedKeywords) {
-        for part in &self.parts {
-            println!(" {}",part.explain(words,keywords));
+    /// If the part at this index in [Self::parts] is a spelling correction, the details.
+    pub fn correction(&self,part:usize) -> Option<&Correction> {
+        self.corrections.iter().find(|c|c.part==part)
+    }
+
+    /// Group the parts that are alternatives for the same text. Each element is a range of indices into [Self::parts].
+    pub fn alternatives(&self) -> Vec<Range<usize>> {
+        let mut res = vec![];
+        let mut start = 0;
+        for i in 1..=self.spans.len() {
+            if i==self.spans.len() || self.spans[i]!=self.spans[start] {
+                res.push(start..i);
+                start=i;
+            }
+        }
+        res
+    }
+
+    /// The mean of the vectors of the known words, a simple embedding of the sentence that can be compared by cosine.
+    /// None if the word file has no vectors or there are no known words.
+    pub fn embedding(&self,words:&WordsInFile) -> Option<WordVec> {
+        let known : Vec<WordIndex> = self.parts.iter().filter_map(|part|if let SentencePart::Known(word) = part { Some(*word) } else { None }).collect();
+        words.mean_vector(&known)
+    }
+
+    pub fn explain<T:Tokenizer>(&self,tokenizer:&T) {
+        for (i,part) in self.parts.iter().enumerate() {
+            match self.correction(i) {
+                Some(correction) => println!(" {} (corrected from {}, distance {})",tokenizer.explain(part),correction.original,correction.distance),
+                None => println!(" {}",tokenizer.explain(part)),
+            }
         }
     }
 }
\ No newline at end of file
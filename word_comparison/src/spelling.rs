@@ -0,0 +1,143 @@
+//! Suggest corrections for misspelt words, such as "vaccine" for "vacinne" or "morrison" for "morisson".
+//!
+//! A misspelt word is tokenized as an unknown word, which only matches the exact same typo. A [SpellingCorrector]
+//! finds the general vocabulary word or listed keyword alias within a small edit distance, which is then added as
+//! an alternative token for the same text (see [TokenizedSentence::correct_spelling](crate::sentences::TokenizedSentence::correct_spelling)).
+//!
+//! This uses the symmetric delete method of SymSpell : every string that can be made by deleting up to
+//! [SpellingOptions::max_distance] characters from each candidate is stored in a hash table. Deleting up to the same number
+//! of characters from the misspelt word then finds all candidates that could be within that distance, without comparing
+//! against the whole vocabulary.
+
+use std::collections::{HashMap, HashSet};
+use crate::listed_keywords::{ListedKeywordIndex, ListedKeywords};
+use crate::sentences::SentencePart;
+use crate::word::{WordIndex, WordSource};
+use crate::word_file::WordsInFile;
+
+/// Choices for spelling correction.
+#[derive(Clone, Debug)]
+pub struct SpellingOptions {
+    /// The maximum number of edits (inserting, deleting or changing a character, or swapping two adjacent characters).
+    pub max_distance : usize,
+    /// Only this many of the most common general vocabulary words can be corrections, as the table takes a lot of memory.
+    /// Listed keywords can always be corrections.
+    pub max_words : usize,
+    /// Words with fewer characters than this are not corrected, as short words are often a couple of edits from many others.
+    pub min_length : usize,
+    /// The score for a match via a corrected word is multiplied by this.
+    pub penalty : f64,
+}
+
+impl Default for SpellingOptions {
+    fn default() -> Self { SpellingOptions{ max_distance: 2, max_words: 50000, min_length: 4, penalty: 0.5 } }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Candidate {
+    Known(WordIndex),
+    Listed(ListedKeywordIndex),
+}
+
+pub struct SpellingCorrector {
+    options : SpellingOptions,
+    /// The text of each candidate correction, as characters, and what it is.
+    candidates : Vec<(Vec<char>,Candidate)>,
+    /// For each string made by deleting up to max_distance characters from a candidate, the indices of those candidates.
+    deletes : HashMap<String,Vec<u32>>,
+}
+
+/// Whether a word is worth correcting, or being a correction. Excludes numbers, punctuation and phrases.
+fn is_correctable(word:&str,min_length:usize) -> bool {
+    word.chars().count()>=min_length && word.chars().all(char::is_alphabetic)
+}
+
+/// Add all strings made by deleting up to max_deletes characters from word to res.
+fn add_deletes(word:&[char],max_deletes:usize,res:&mut HashSet<String>) {
+    if res.insert(word.iter().collect()) && max_deletes>0 && !word.is_empty() {
+        for i in 0..word.len() {
+            let mut shorter = word.to_vec();
+            shorter.remove(i);
+            add_deletes(&shorter,max_deletes-1,res);
+        }
+    }
+}
+
+/// The optimal string alignment distance : the number of insertions, deletions, substitutions and swaps of adjacent characters.
+fn edit_distance(a:&[char],b:&[char]) -> usize {
+    let mut d = vec![vec![0;b.len()+1];a.len()+1];
+    for (i,row) in d.iter_mut().enumerate() { row[0]=i; }
+    for (j,cell) in d[0].iter_mut().enumerate() { *cell=j; }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i-1]==b[j-1] { 0 } else { 1 };
+            d[i][j] = (d[i-1][j]+1).min(d[i][j-1]+1).min(d[i-1][j-1]+cost);
+            if i>1 && j>1 && a[i-1]==b[j-2] && a[i-2]==b[j-1] { d[i][j] = d[i][j].min(d[i-2][j-2]+1); }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+impl SpellingCorrector {
+    /// Build the table of corrections. The listed keywords should be normalized the same way as the words.
+    pub fn new(words:&WordsInFile,keywords:&ListedKeywords,options:SpellingOptions) -> Self {
+        let mut candidates : Vec<(Vec<char>,Candidate)> = vec![];
+        for (alias,keyword) in keywords.normalized_aliases() {
+            if is_correctable(alias,options.min_length) { candidates.push((alias.chars().collect(),Candidate::Listed(keyword))); }
+        }
+        for word in words.all_indices().take(options.max_words) {
+            if is_correctable(words.word(word),options.min_length) { candidates.push((words.word(word).chars().collect(),Candidate::Known(word))); }
+        }
+        let mut deletes : HashMap<String,Vec<u32>> = HashMap::new();
+        for (i,(text,_)) in candidates.iter().enumerate() {
+            let mut variants = HashSet::new();
+            add_deletes(text,options.max_distance,&mut variants);
+            for variant in variants { deletes.entry(variant).or_default().push(i as u32); }
+        }
+        SpellingCorrector{options,candidates,deletes}
+    }
+
+    pub fn penalty(&self) -> f64 { self.options.penalty }
+
+    /// Find the closest correction to the (normalized) word, and the edit distance to it.
+    /// Ties go to listed keywords, then more common words.
+    pub fn correct(&self,word:&str) -> Option<(SentencePart,usize)> {
+        if !is_correctable(word,self.options.min_length) { return None; }
+        let word : Vec<char> = word.chars().collect();
+        let mut variants = HashSet::new();
+        add_deletes(&word,self.options.max_distance,&mut variants);
+        let mut best : Option<(usize,u32)> = None;
+        for variant in variants {
+            for &candidate in self.deletes.get(&variant).map(|v|v.as_slice()).unwrap_or(&[]) {
+                let distance = edit_distance(&word,&self.candidates[candidate as usize].0);
+                if distance<=self.options.max_distance && best.map(|best|(distance,candidate)<best).unwrap_or(true) { best=Some((distance,candidate)); }
+            }
+        }
+        best.map(|(distance,candidate)|{
+            let part = match self.candidates[candidate as usize].1 {
+                Candidate::Known(word) => SentencePart::Known(word),
+                Candidate::Listed(keyword) => SentencePart::Listed(keyword),
+            };
+            (part,distance)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{keywords_from, small_word_file};
+
+    #[test]
+    fn corrections() {
+        let words = WordsInFile::read_word_file(small_word_file("spelling")).unwrap();
+        let keywords = keywords_from("spelling","Prime Minister,Morrison\n");
+        let corrector = SpellingCorrector::new(&words,&keywords,SpellingOptions::default());
+        assert!(matches!(corrector.correct("vacinne"),Some((SentencePart::Known(w),2)) if words.word(w)=="vaccine"));
+        assert!(matches!(corrector.correct("morisson"),Some((SentencePart::Listed(ListedKeywordIndex(0)),2))));
+        assert!(matches!(corrector.correct("kitetn"),Some((SentencePart::Known(w),1)) if words.word(w)=="kitten"));
+        assert!(corrector.correct("zebra").is_none());
+        assert!(corrector.correct("dgo").is_none()); // too short.
+        assert_eq!(edit_distance(&['a','b','c'],&['a','c','b','d']),2);
+    }
+}
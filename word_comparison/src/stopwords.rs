@@ -0,0 +1,87 @@
+//! Which general vocabulary words are too common to be worth indexing or matching on, such as "the" or "what".
+//!
+//! The same policy must be used when adding questions to the database and when searching it, otherwise
+//! a word could be searched for that was never indexed. Listed keywords are never stopwords, whatever the policy.
+
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::path::Path;
+use crate::sentences::SentencePart;
+use crate::tokenizer::Tokenizer;
+use crate::word::{WordIndex, WordSource};
+use crate::word_file::WordsInFile;
+
+#[derive(Clone, Debug)]
+pub enum StopwordPolicy {
+    /// The given number of most common words (those with a smaller [WordIndex]) are stopwords.
+    Rank(u32),
+    /// The listed words are stopwords. Words are stored as text so the policy stays valid if the vocabulary is replaced.
+    Words(HashSet<String>),
+}
+
+impl Default for StopwordPolicy {
+    fn default() -> Self { StopwordPolicy::Rank(400) }
+}
+
+impl StopwordPolicy {
+    pub const STD_LOCATION : &'static str = "Stopwords.txt";
+
+    /// Whether a word in the general vocabulary should be neither indexed nor matched on.
+    pub fn is_stopword(&self,word:WordIndex,words:&WordsInFile) -> bool {
+        match self {
+            StopwordPolicy::Rank(rank) => word.0<*rank,
+            StopwordPolicy::Words(stopwords) => stopwords.contains(words.word(word)),
+        }
+    }
+
+    /// Read a list of stopwords, one per line, normalized as in the vocabulary. Blank lines and lines starting with # are ignored.
+    pub fn load_word_list<P:AsRef<Path>>(path:P,words:&WordsInFile) -> std::io::Result<Self> {
+        let mut stopwords = HashSet::new();
+        for line in std::io::BufReader::new(std::fs::File::open(path)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') { stopwords.insert(words.normalization().normalize(line)); }
+        }
+        Ok(StopwordPolicy::Words(stopwords))
+    }
+
+    /// The general vocabulary words that occur in more than max_fraction of the questions are stopwords.
+    /// This adapts to the questions actually asked, but needs a reasonable number of questions to be meaningful.
+    pub fn from_document_frequency<'a,I:IntoIterator<Item=&'a str>,T:Tokenizer>(questions:I,tokenizer:&T,max_fraction:f64) -> Self {
+        let mut document_frequency : HashMap<WordIndex,usize> = HashMap::new();
+        let mut num_questions = 0;
+        for question in questions {
+            num_questions+=1;
+            let in_question : HashSet<WordIndex> = tokenizer.tokenize(question).parts.into_iter().filter_map(|part|if let SentencePart::Known(word) = part { Some(word) } else { None }).collect();
+            for word in in_question { *document_frequency.entry(word).or_default()+=1; }
+        }
+        let limit = max_fraction*num_questions as f64;
+        StopwordPolicy::Words(document_frequency.into_iter().filter(|&(_,count)|count as f64>limit).map(|(word,_)|{ let (words,local) = tokenizer.vocabulary(word); words.word(local).to_string() }).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::StandardTokenizer;
+    use crate::test_util::{keywords_from, small_word_file};
+
+    #[test]
+    fn rank() {
+        let words = WordsInFile::read_word_file(small_word_file("stopwords_rank")).unwrap();
+        let policy = StopwordPolicy::Rank(3);
+        assert!(policy.is_stopword(WordIndex(2),&words));
+        assert!(!policy.is_stopword(WordIndex(3),&words));
+    }
+
+    #[test]
+    fn document_frequency() {
+        let words = WordsInFile::read_word_file(small_word_file("stopwords")).unwrap();
+        let keywords = keywords_from("stopwords","Covid,Coronavirus\n");
+        let questions = ["where is the covid cat?","the covid dog","where is the covid vaccine"];
+        let policy = StopwordPolicy::from_document_frequency(questions,&StandardTokenizer::new(&words,&keywords),0.5);
+        // "covid" is in every question but is a listed keyword, "is" is not in the vocabulary, and "?" is in only one question.
+        let expected : HashSet<String> = ["the","where"].iter().map(|w|w.to_string()).collect();
+        assert!(matches!(&policy,StopwordPolicy::Words(stopwords) if *stopwords==expected));
+    }
+}
@@ -0,0 +1,141 @@
+//! Hand maintained corrections to the synonyms produced from word vectors.
+//!
+//! Word vectors put words that occur in similar contexts close together, which includes antonyms
+//! ("increase" and "decrease") and words that merely co-occur ("labor" and "liberal"). For
+//! political questions these give bad matches, so a csv file can adjust the synonyms, one change per line:
+//! ```text
+//! # comments start with a hash
+//! add,rise,increase,0.9
+//! remove,increase,decrease
+//! block,liberal
+//! ```
+//! * `add,a,b,score` makes a and b synonyms of each other with the given goodness score (replacing any existing score).
+//! * `remove,a,b` stops a and b being synonyms of each other.
+//! * `block,a` stops a being a synonym of anything. Its own synonyms are unaffected.
+//!
+//! The overlay can be applied when the vocabulary file is built (see [WordFileOptions](crate::word_file::WordFileOptions)),
+//! or when it is loaded (see [WordsInFile::apply_synonym_overlay](crate::word_file::WordsInFile::apply_synonym_overlay)).
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use csv::ReaderBuilder;
+use crate::near_words::WordAndValue;
+use crate::normalize::Normalization;
+use crate::word::{WordIndex, WordSource};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SynonymOverlayEntry {
+    Add{ a:String, b:String, score:f32 },
+    Remove{ a:String, b:String },
+    Block(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SynonymOverlay {
+    pub entries : Vec<SynonymOverlayEntry>,
+}
+
+fn bad_line(line:usize,problem:&str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData,format!("Synonym overlay line {} : {}",line,problem))
+}
+
+impl SynonymOverlay {
+    pub const STD_LOCATION : &'static str = "SynonymOverlay.csv";
+
+    /// Load the overlay. The words are kept as written, and normalized when [resolved](Self::resolve) against a vocabulary.
+    pub fn load<P:AsRef<Path>>(path:P) -> std::io::Result<Self> {
+        let mut entries = vec![];
+        let mut reader = ReaderBuilder::new().flexible(true).has_headers(false).comment(Some(b'#')).trim(csv::Trim::All).from_path(path)?;
+        for result in reader.records() {
+            let record = result?;
+            let line = record.position().map(|p|p.line() as usize).unwrap_or(0);
+            let field = |i:usize| record.get(i).map(|s|s.to_string()).ok_or_else(||bad_line(line,"too few fields"));
+            let entry = match record.get(0).unwrap_or("") {
+                "add" => SynonymOverlayEntry::Add{a:field(1)?,b:field(2)?,score:field(3)?.parse().ok().filter(|score:&f32|score.is_finite()).ok_or_else(||bad_line(line,"score is not a finite number"))?},
+                "remove" => SynonymOverlayEntry::Remove{a:field(1)?,b:field(2)?},
+                "block" => SynonymOverlayEntry::Block(field(1)?),
+                _ => return Err(bad_line(line,"should start with add, remove or block")),
+            };
+            entries.push(entry);
+        }
+        Ok(SynonymOverlay{entries})
+    }
+
+    /// Convert the words to indices in the given vocabulary, after normalizing them in the same way as the vocabulary.
+    /// Words that are not in the vocabulary are listed in [ResolvedSynonymOverlay::unknown_words] and otherwise ignored.
+    pub fn resolve<W:WordSource>(&self,words:&W,normalization:Normalization) -> ResolvedSynonymOverlay {
+        let mut res = ResolvedSynonymOverlay::default();
+        let mut lookup = |word:&str| {
+            let index = words.index(&normalization.normalize(word));
+            if index.is_none() && !res.unknown_words.iter().any(|w|w==word) { res.unknown_words.push(word.to_string()); }
+            index
+        };
+        let mut added : Vec<(WordIndex,WordIndex,f32)> = vec![];
+        let mut removed = HashSet::new();
+        let mut blocked = HashSet::new();
+        for entry in &self.entries {
+            match entry {
+                SynonymOverlayEntry::Add { a, b, score } => if let (Some(a),Some(b)) = (lookup(a),lookup(b)) { added.push((a,b,*score)); },
+                SynonymOverlayEntry::Remove { a, b } => if let (Some(a),Some(b)) = (lookup(a),lookup(b)) { removed.insert((a,b)); removed.insert((b,a)); },
+                SynonymOverlayEntry::Block(a) => if let Some(a) = lookup(a) { blocked.insert(a); },
+            }
+        }
+        for (a,b,score) in added {
+            res.added.entry(a).or_default().push(WordAndValue{word:b,value:score});
+            res.added.entry(b).or_default().push(WordAndValue{word:a,value:score});
+        }
+        res.removed=removed;
+        res.blocked=blocked;
+        res
+    }
+}
+
+/// A [SynonymOverlay] with words converted to indices in some vocabulary.
+#[derive(Clone, Debug, Default)]
+pub struct ResolvedSynonymOverlay {
+    added : HashMap<WordIndex,Vec<WordAndValue>>,
+    /// pairs (a,b) where b should not be a synonym of a.
+    removed : HashSet<(WordIndex,WordIndex)>,
+    blocked : HashSet<WordIndex>,
+    /// Words in the overlay that are not in the vocabulary.
+    pub unknown_words : Vec<String>,
+}
+
+impl ResolvedSynonymOverlay {
+    /// Adjust the synonyms of a word, returning them best first.
+    pub fn apply(&self,word:WordIndex,synonyms:Vec<WordAndValue>) -> Vec<WordAndValue> {
+        let added = self.added.get(&word).map(|v|v.as_slice()).unwrap_or(&[]);
+        let mut res : Vec<WordAndValue> = synonyms.into_iter().filter(|s|!added.iter().any(|a|a.word==s.word)).chain(added.iter().cloned())
+            .filter(|s|s.word!=word && !self.blocked.contains(&s.word) && !self.removed.contains(&(word,s.word))).collect();
+        res.sort_by(|a,b|b.value.total_cmp(&a.value));
+        res
+    }
+
+    pub fn is_empty(&self) -> bool { self.added.is_empty() && self.removed.is_empty() && self.blocked.is_empty() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word_file::WordsInFile;
+    use crate::test_util::{small_word_file, temp_path};
+
+    #[test]
+    fn overlay_applied_at_load() {
+        let path = temp_path("overlay.csv");
+        std::fs::write(&path,"# test\nadd,Cat,vaccine,0.5\nremove,cat,DOG\nblock, kitten\nblock,unknownword\n").unwrap();
+        let overlay = SynonymOverlay::load(&path).unwrap();
+        assert_eq!(overlay.entries[2],SynonymOverlayEntry::Block("kitten".to_string()));
+        let mut words = WordsInFile::read_word_file(small_word_file("overlay")).unwrap();
+        assert_eq!(words.apply_synonym_overlay(&overlay),vec!["unknownword".to_string()]);
+        let cat = words.synonyms(words.index("cat").unwrap());
+        let vaccine = words.index("vaccine").unwrap();
+        assert_eq!(cat[0],WordAndValue{word:vaccine,value:0.5});
+        assert!(cat.iter().all(|s|s.word!=words.index("dog").unwrap() && s.word!=words.index("kitten").unwrap()));
+        assert!(words.synonyms(vaccine).iter().any(|s|s.word==words.index("cat").unwrap()));
+        std::fs::write(&path,"replace,cat,dog\n").unwrap();
+        assert!(SynonymOverlay::load(&path).is_err());
+        std::fs::write(&path,"add,cat,dog,0.5\nadd,cat,kitten,nan\n").unwrap();
+        assert!(SynonymOverlay::load(&path).unwrap_err().to_string().contains("line 2"));
+    }
+}
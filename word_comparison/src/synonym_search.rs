@@ -0,0 +1,212 @@
+//! Find the nearest words (by cosine) to each word, which become its synonyms.
+//!
+//! Exact search compares every word against every other word, which is quadratic and takes hours
+//! for large vocabularies even spread over all cores. Approximate search uses random projection
+//! locality sensitive hashing: each word is hashed by which side of some random hyperplanes it is on,
+//! and only words sharing a hash in at least one of several tables are compared. This misses some
+//! true neighbours, so [measure_recall] compares it against exact search on a sample.
+
+use std::collections::HashMap;
+use rayon::prelude::*;
+use crate::near_words::{SmallestN, WordAndValue};
+use crate::word::{WordIndex, WordVecs};
+
+/// How to find the nearest words to each word.
+#[derive(Clone, Debug)]
+pub enum SynonymSearch {
+    /// Compare every word against every other word.
+    Exact,
+    /// Only compare words that hash to the same bucket in some table.
+    Approximate(LshParameters),
+}
+
+/// Parameters for random projection locality sensitive hashing.
+#[derive(Clone, Debug)]
+pub struct LshParameters {
+    /// Number of independent hash tables. More tables gives better recall and slower search.
+    pub num_tables : usize,
+    /// Number of random hyperplanes per table. There are 2^bits buckets per table. More bits gives smaller buckets, faster search and worse recall.
+    pub bits_per_table : usize,
+    /// Seed for the random hyperplanes, so builds are reproducible.
+    pub seed : u64,
+}
+
+impl LshParameters {
+    /// Reasonable parameters for a vocabulary of the given size, aiming at about 50 words per bucket.
+    pub fn suggested(num_words:usize) -> Self {
+        let bits_per_table = ((num_words/50).max(2) as f64).log2().floor() as usize;
+        LshParameters{ num_tables: 24, bits_per_table: bits_per_table.clamp(1,24), seed: 0x5eed }
+    }
+}
+
+/// The result of comparing approximate search against exact search.
+#[derive(Clone, Debug)]
+pub struct RecallReport {
+    /// Number of words compared.
+    pub sample_size : usize,
+    /// Fraction of the exact synonyms (over all the sampled words) that the approximate search found.
+    pub recall : f64,
+}
+
+/// A simple deterministic pseudo random number generator (splitmix64). Quality is more than sufficient for choosing hyperplanes.
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed:u64) -> Self { SplitMix64(seed) }
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+    /// uniform in (0,1]
+    fn next_f64(&mut self) -> f64 { ((self.next_u64()>>11) as f64+1.0)/((1u64<<53) as f64) }
+    /// standard normal, by Box-Muller.
+    fn next_gaussian(&mut self) -> f64 {
+        (-2.0*self.next_f64().ln()).sqrt()*(2.0*std::f64::consts::PI*self.next_f64()).cos()
+    }
+}
+
+/// The best num_synonyms words (other than word itself) out of the candidates, best first, with value being the cosine.
+fn best_of<I:Iterator<Item=WordIndex>>(word:WordIndex,candidates:I,wordvecs:&WordVecs,num_synonyms:usize) -> Vec<WordAndValue> {
+    let vec = wordvecs.get(word);
+    let mut best_cosine = SmallestN::new(num_synonyms);
+    for comparison in candidates {
+        if comparison!=word {
+            best_cosine.add(WordAndValue{ word: comparison, value: -vec.cosine(wordvecs.get(comparison)) as f32 });
+        }
+    }
+    best_cosine.into_sorted_vec().into_iter().map(|w|WordAndValue{word:w.word,value:-w.value}).collect()
+}
+
+fn all_words(wordvecs:&WordVecs) -> impl Iterator<Item=WordIndex> {
+    (0..wordvecs.len()).map(|i|WordIndex(i as u32))
+}
+
+/// The exact best num_synonyms synonyms of a single word.
+pub fn exact_synonyms(word:WordIndex,wordvecs:&WordVecs,num_synonyms:usize) -> Vec<WordAndValue> {
+    best_of(word,all_words(wordvecs),wordvecs,num_synonyms)
+}
+
+/// Find the best num_synonyms synonyms of every word, best first, with value being the cosine. Uses all the threads in the current rayon pool.
+/// Approximate search falls back to exact search for a word whose buckets have too few other words in them, so every word
+/// gets num_synonyms synonyms (if there are that many other words) either way.
+pub fn find_synonyms(wordvecs:&WordVecs,num_synonyms:usize,search:&SynonymSearch) -> Vec<Vec<WordAndValue>> {
+    match search {
+        SynonymSearch::Exact => {
+            (0..wordvecs.len()).into_par_iter().map(|i|exact_synonyms(WordIndex(i as u32),wordvecs,num_synonyms)).collect()
+        }
+        SynonymSearch::Approximate(parameters) => {
+            let tables = LshTables::new(wordvecs,parameters);
+            (0..wordvecs.len()).into_par_iter().map(|i|{
+                let word = WordIndex(i as u32);
+                let best = best_of(word,tables.candidates(word).into_iter(),wordvecs,num_synonyms);
+                if best.len()<num_synonyms.min(wordvecs.len()-1) { exact_synonyms(word,wordvecs,num_synonyms) } else { best }
+            }).collect()
+        }
+    }
+}
+
+/// Compare the found synonyms against exact search for sample_size different pseudo randomly chosen words.
+pub fn measure_recall(wordvecs:&WordVecs,found:&[Vec<WordAndValue>],num_synonyms:usize,sample_size:usize,seed:u64) -> RecallReport {
+    let mut random = SplitMix64::new(seed);
+    let sample_size = sample_size.min(wordvecs.len());
+    // the start of a Fisher-Yates shuffle, so no word is chosen twice.
+    let mut sample : Vec<WordIndex> = (0..wordvecs.len() as u32).map(WordIndex).collect();
+    for i in 0..sample_size {
+        let j = i+(random.next_u64()%(sample.len()-i) as u64) as usize;
+        sample.swap(i,j);
+    }
+    sample.truncate(sample_size);
+    let (found_count,exact_count) = sample.par_iter().map(|&word|{
+        let exact = exact_synonyms(word,wordvecs,num_synonyms);
+        let found = &found[word.0 as usize];
+        (exact.iter().filter(|e|found.iter().any(|f|f.word==e.word)).count(),exact.len())
+    }).reduce(||(0,0),|a,b|(a.0+b.0,a.1+b.1));
+    RecallReport{ sample_size, recall: if exact_count==0 { 1.0 } else { found_count as f64/exact_count as f64 } }
+}
+
+/// The hash tables for approximate search. Each table has its own random hyperplanes, and maps a hash (bit i set if the word is on the positive side of hyperplane i) to the words with that hash.
+struct LshTables {
+    /// for each table, the hash of each word.
+    hashes : Vec<Vec<u32>>,
+    /// for each table, the words with each hash.
+    buckets : Vec<HashMap<u32,Vec<WordIndex>>>,
+}
+
+impl LshTables {
+    fn new(wordvecs:&WordVecs,parameters:&LshParameters) -> Self {
+        let dimensions = if wordvecs.is_empty() { 0 } else { wordvecs.get(WordIndex(0)).values().len() };
+        let mut random = SplitMix64::new(parameters.seed);
+        // for each table, for each bit, the hyperplane normal.
+        let hyperplanes : Vec<Vec<Vec<f64>>> = (0..parameters.num_tables).map(|_|(0..parameters.bits_per_table).map(|_|(0..dimensions).map(|_|random.next_gaussian()).collect()).collect()).collect();
+        let hashes : Vec<Vec<u32>> = hyperplanes.par_iter().map(|planes|all_words(wordvecs).map(|word|hash(wordvecs.get(word).values(),planes)).collect()).collect();
+        let buckets = hashes.iter().map(|hashes|{
+            let mut buckets : HashMap<u32,Vec<WordIndex>> = HashMap::new();
+            for (i,&hash) in hashes.iter().enumerate() { buckets.entry(hash).or_default().push(WordIndex(i as u32)); }
+            buckets
+        }).collect();
+        LshTables{hashes,buckets}
+    }
+
+    /// All words sharing a bucket with the given word in some table, without duplicates.
+    fn candidates(&self,word:WordIndex) -> Vec<WordIndex> {
+        let mut res = vec![];
+        for (table,hashes) in self.hashes.iter().enumerate() {
+            if let Some(bucket) = self.buckets[table].get(&hashes[word.0 as usize]) { res.extend_from_slice(bucket); }
+        }
+        res.sort_unstable();
+        res.dedup();
+        res
+    }
+}
+
+fn hash(vec:&[f64],planes:&[Vec<f64>]) -> u32 {
+    let mut res = 0;
+    for (bit,plane) in planes.iter().enumerate() {
+        let dot : f64 = vec.iter().zip(plane.iter()).map(|(a,b)|a*b).sum();
+        if dot>0.0 { res|=1<<bit; }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word::WordVec;
+
+    /// Random vectors in clusters, so that there are real near neighbours to find.
+    fn clustered_vectors(num_words:usize) -> WordVecs {
+        let mut random = SplitMix64::new(7);
+        let centres : Vec<Vec<f64>> = (0..num_words/10).map(|_|(0..20).map(|_|random.next_gaussian()).collect()).collect();
+        let mut wordvecs = WordVecs::default();
+        for i in 0..num_words {
+            wordvecs.push(WordVec::new(centres[i%centres.len()].iter().map(|c|c+0.3*random.next_gaussian()).collect()));
+        }
+        wordvecs
+    }
+
+    #[test]
+    fn approximate_search_finds_most_neighbours() {
+        let wordvecs = clustered_vectors(1000);
+        let exact = find_synonyms(&wordvecs,5,&SynonymSearch::Exact);
+        assert_eq!(measure_recall(&wordvecs,&exact,5,100,1).recall,1.0);
+        assert!(exact[0].windows(2).all(|w|w[0].value>=w[1].value));
+        let approximate = find_synonyms(&wordvecs,5,&SynonymSearch::Approximate(LshParameters::suggested(1000)));
+        let report = measure_recall(&wordvecs,&approximate,5,200,1);
+        assert_eq!(report.sample_size,200);
+        assert!(report.recall>0.8,"recall {}",report.recall);
+    }
+
+    #[test]
+    fn approximate_search_fills_small_buckets() {
+        let wordvecs = clustered_vectors(100);
+        let tiny_buckets = LshParameters{ num_tables: 1, bits_per_table: 16, seed: 3 };
+        let approximate = find_synonyms(&wordvecs,5,&SynonymSearch::Approximate(tiny_buckets));
+        assert!(approximate.iter().all(|synonyms|synonyms.len()==5));
+        let report = measure_recall(&wordvecs,&approximate,5,100,1);
+        assert_eq!(report.sample_size,100);
+        assert_eq!(report.recall,1.0); // the buckets are so small that the words fall back to exact search.
+    }
+}
@@ -0,0 +1,44 @@
+//! Fixtures shared by the tests of several modules.
+
+use std::path::PathBuf;
+use crate::listed_keywords::ListedKeywords;
+use crate::word::{read_glove, MemoryWords, WordVecs};
+use crate::word_file::write_word_file;
+
+/// A file in the temporary directory that will not clash with other tests.
+pub fn temp_path(name:&str) -> PathBuf {
+    std::env::temp_dir().join(format!("word_comparison_test_{}_{}",std::process::id(),name))
+}
+
+/// Small glove style vectors, most common first.
+pub const SMALL_GLOVE : &str = "the 1 0 0\n? 0.9 0.1 0\nwhere 0 1 0\ncat 0 0 1\ndog 0 0.1 1\nkitten 0.1 0 1\nwherefore 0 1 0.1\nvaccine 1 1 0\nvaccination 1 1 0.1\n";
+
+/// Make a word file from [SMALL_GLOVE] with 3 synonyms per word, returning its path.
+pub fn small_word_file(name:&str) -> PathBuf {
+    word_file_from_glove(name,SMALL_GLOVE,3)
+}
+
+/// Read glove style vectors from text.
+pub fn glove_from_text(name:&str,glove_text:&str) -> (MemoryWords,WordVecs) {
+    let glove = temp_path(&format!("{}.txt",name));
+    std::fs::write(&glove,glove_text).unwrap();
+    read_glove(&glove,None).unwrap()
+}
+
+/// The words and vectors in [SMALL_GLOVE].
+pub fn small_glove(name:&str) -> (MemoryWords,WordVecs) { glove_from_text(name,SMALL_GLOVE) }
+
+/// Make a word file from glove style vectors, returning its path.
+pub fn word_file_from_glove(name:&str,glove_text:&str,num_synonyms:u32) -> PathBuf {
+    let (words,wordvecs) = glove_from_text(name,glove_text);
+    let path = temp_path(&format!("{}.bin",name));
+    write_word_file(&path,&words,&wordvecs,num_synonyms).unwrap();
+    path
+}
+
+/// Listed keywords from the csv text.
+pub fn keywords_from(name:&str,csv:&str) -> ListedKeywords {
+    let path = temp_path(&format!("{}_keywords.csv",name));
+    std::fs::write(&path,csv).unwrap();
+    ListedKeywords::load(&path).unwrap()
+}
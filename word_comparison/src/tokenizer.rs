@@ -0,0 +1,85 @@
+//! Ways of splitting a question into [SentencePart]s.
+//!
+//! The comparison functions (see [comparison_list](crate::comparison_list)) are generic over the [Tokenizer] so
+//! that stages can be added to or replaced in the standard algorithm, such as [SpellingCorrection], and so tests can
+//! use a made up vocabulary. The same tokenizer should be used for adding questions to the database and searching it.
+
+use crate::listed_keywords::ListedKeywords;
+use crate::sentences::{SentencePart, TokenizedSentence};
+use crate::spelling::SpellingCorrector;
+use crate::word::WordIndex;
+use crate::word_file::WordsInFile;
+
+pub trait Tokenizer {
+    /// Split the text into parts, each with the range of bytes in the text it came from.
+    fn tokenize(&self,text:&str) -> TokenizedSentence;
+    /// Split a question stored in the database into parts, the way it was when it was added. This leaves out query only
+    /// stages such as [SpellingCorrection], so questions are indexed the same whichever tokenizer they are added with.
+    fn tokenize_stored(&self,text:&str) -> TokenizedSentence { self.tokenize(text) }
+    /// The general vocabulary that [SentencePart::Known] parts refer to, used for scoring and synonyms.
+    /// If there are several (see [vocabulary](Self::vocabulary)), the one used by default.
+    fn words(&self) -> &WordsInFile;
+    /// The general vocabulary that a [SentencePart::Known] word is in, and its index in that vocabulary.
+    /// Tokenizers with several vocabularies, such as [MultilingualTokenizer](crate::multilingual::MultilingualTokenizer),
+    /// give each vocabulary its own range of indices, so the same word index is never two different words.
+    fn vocabulary(&self,word:WordIndex) -> (&WordsInFile,WordIndex) { (self.words(),word) }
+    /// The inverse of [Self::vocabulary]: the tokenizer's index for the word with index local in the vocabulary that word is in.
+    fn global_index(&self,_word:WordIndex,local:WordIndex) -> WordIndex { local }
+    /// The listed keywords that [SentencePart::Listed] parts refer to.
+    fn keywords(&self) -> &ListedKeywords;
+    /// A human readable description of a part, for debugging.
+    fn explain(&self,part:&SentencePart) -> String;
+}
+
+impl <T:Tokenizer+?Sized> Tokenizer for &T {
+    fn tokenize(&self,text:&str) -> TokenizedSentence { (**self).tokenize(text) }
+    fn tokenize_stored(&self,text:&str) -> TokenizedSentence { (**self).tokenize_stored(text) }
+    fn words(&self) -> &WordsInFile { (**self).words() }
+    fn vocabulary(&self,word:WordIndex) -> (&WordsInFile,WordIndex) { (**self).vocabulary(word) }
+    fn global_index(&self,word:WordIndex,local:WordIndex) -> WordIndex { (**self).global_index(word,local) }
+    fn keywords(&self) -> &ListedKeywords { (**self).keywords() }
+    fn explain(&self,part:&SentencePart) -> String { (**self).explain(part) }
+}
+
+/// The standard algorithm, described in [TokenizedSentence::tokenize].
+#[derive(Clone, Copy)]
+pub struct StandardTokenizer<'a> {
+    pub words : &'a WordsInFile,
+    pub keywords : &'a ListedKeywords,
+}
+
+impl <'a> StandardTokenizer<'a> {
+    pub fn new(words:&'a WordsInFile,keywords:&'a ListedKeywords) -> Self { StandardTokenizer{words,keywords} }
+}
+
+impl Tokenizer for StandardTokenizer<'_> {
+    fn tokenize(&self,text:&str) -> TokenizedSentence { TokenizedSentence::tokenize(text,self.words,self.keywords) }
+    fn words(&self) -> &WordsInFile { self.words }
+    fn keywords(&self) -> &ListedKeywords { self.keywords }
+    fn explain(&self,part:&SentencePart) -> String { part.explain(self.words,self.keywords) }
+}
+
+/// A stage after another tokenizer that adds spelling corrections of unknown words (see [TokenizedSentence::correct_spelling]).
+/// This is usually only wanted for questions being searched for, not those added to the database.
+pub struct SpellingCorrection<'a,T:Tokenizer> {
+    pub inner : T,
+    pub corrector : &'a SpellingCorrector,
+}
+
+impl <'a,T:Tokenizer> SpellingCorrection<'a,T> {
+    pub fn new(inner:T,corrector:&'a SpellingCorrector) -> Self { SpellingCorrection{inner,corrector} }
+}
+
+impl <T:Tokenizer> Tokenizer for SpellingCorrection<'_,T> {
+    fn tokenize(&self,text:&str) -> TokenizedSentence {
+        let mut tokenized = self.inner.tokenize(text);
+        tokenized.correct_spelling(self.corrector);
+        tokenized
+    }
+    fn tokenize_stored(&self,text:&str) -> TokenizedSentence { self.inner.tokenize_stored(text) }
+    fn words(&self) -> &WordsInFile { self.inner.words() }
+    fn vocabulary(&self,word:WordIndex) -> (&WordsInFile,WordIndex) { self.inner.vocabulary(word) }
+    fn global_index(&self,word:WordIndex,local:WordIndex) -> WordIndex { self.inner.global_index(word,local) }
+    fn keywords(&self) -> &ListedKeywords { self.inner.keywords() }
+    fn explain(&self,part:&SentencePart) -> String { self.inner.explain(part) }
+}
@@ -0,0 +1,210 @@
+//! Recognise numbers, amounts of money, percentages, years, dates and references to legislation in a question.
+//!
+//! These are tokenized as a [TypedValue] rather than as words, and matched on their value, so "$5b", "$5 billion"
+//! and "five billion dollars" are all the same. Text is parsed after normalization, so it is lower case and dashes are "-".
+//!
+//! Number words ("five billion") are only recognised before a scale, percentage or currency, so ordinary uses
+//! of words like "one" stay as general vocabulary.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypedValue {
+    Number(f64),
+    /// An amount of money such as "$5 billion". Dollars of unspecified country are "$".
+    Money{ currency : String, amount : f64 },
+    Percentage(f64),
+    Year(i32),
+    /// A range of years such as the financial year "2021-22", which is YearRange(2021,2022).
+    YearRange(i32,i32),
+    Date{ year : i32, month : u32, day : u32 },
+    /// A reference to a bill, such as "Bill C-12", by its identifier ("c-12").
+    Legislation(String),
+}
+
+impl TypedValue {
+    /// A string that is the same for two values if and only if they should match, used for indexing.
+    pub fn key(&self) -> String {
+        match self {
+            TypedValue::Number(v) => format!("number:{}",v),
+            TypedValue::Money{currency,amount} => format!("money:{}:{}",currency,amount),
+            TypedValue::Percentage(v) => format!("percent:{}",v),
+            TypedValue::Year(year) => format!("year:{}",year),
+            TypedValue::YearRange(from,to) => format!("years:{}-{}",from,to),
+            TypedValue::Date{year,month,day} => format!("date:{:04}-{:02}-{:02}",year,month,day),
+            TypedValue::Legislation(id) => format!("bill:{}",id),
+        }
+    }
+}
+
+impl fmt::Display for TypedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypedValue::Number(v) => write!(f,"Number {}",v),
+            TypedValue::Money{currency,amount} => write!(f,"Money {} {}",currency,amount),
+            TypedValue::Percentage(v) => write!(f,"Percentage {}%",v),
+            TypedValue::Year(year) => write!(f,"Year {}",year),
+            TypedValue::YearRange(from,to) => write!(f,"Years {} to {}",from,to),
+            TypedValue::Date{year,month,day} => write!(f,"Date {:04}-{:02}-{:02}",year,month,day),
+            TypedValue::Legislation(id) => write!(f,"Bill {}",id),
+        }
+    }
+}
+
+const NUMBER_WORDS : [(&str,f64);28] = [("zero",0.0),("one",1.0),("two",2.0),("three",3.0),("four",4.0),("five",5.0),("six",6.0),("seven",7.0),
+    ("eight",8.0),("nine",9.0),("ten",10.0),("eleven",11.0),("twelve",12.0),("thirteen",13.0),("fourteen",14.0),("fifteen",15.0),("sixteen",16.0),
+    ("seventeen",17.0),("eighteen",18.0),("nineteen",19.0),("twenty",20.0),("thirty",30.0),("forty",40.0),("fifty",50.0),("sixty",60.0),
+    ("seventy",70.0),("eighty",80.0),("ninety",90.0)];
+
+/// Scales that can follow a number. Longest first where one is a prefix of another.
+const SCALES : [(&str,f64);11] = [("thousand",1e3),("million",1e6),("billion",1e9),("trillion",1e12),("hundred",1e2),("bn",1e9),("mn",1e6),("tn",1e12),("k",1e3),("m",1e6),("b",1e9)];
+
+/// Currency symbols that come before an amount, and their canonical names. Longest first where one is a suffix of another.
+const CURRENCY_PREFIXES : [(&str,&str);6] = [("aud$","$"),("au$","$"),("a$","$"),("us$","us$"),("$","$"),("€","€")];
+/// Currency names that come after an amount, and their canonical names.
+const CURRENCY_SUFFIXES : [(&str,&str);6] = [("dollars","$"),("dollar","$"),("aud","$"),("usd","us$"),("euros","€"),("euro","€")];
+
+/// Whether position pos in s is the end of a word, i.e. not followed by a letter or digit.
+fn at_boundary(s:&str,pos:usize) -> bool {
+    !s[pos..].starts_with(char::is_alphanumeric)
+}
+
+/// If s starts with one of the words (followed by a boundary), possibly after a space, return the value and length used.
+fn word_from<T:Copy>(s:&str,table:&[(&str,T)],allow_space:bool) -> Option<(T,usize)> {
+    let (skip,rest) = if allow_space && s.starts_with(' ') { (1,&s[1..]) } else { (0,s) };
+    table.iter().find(|(word,_)|rest.starts_with(word) && at_boundary(rest,word.len())).map(|&(word,value)|(value,skip+word.len()))
+}
+
+/// A number in digits, such as "5", "1,000,000" or "3.5". Returns the value, the length used, and whether it is a plain integer of that many digits.
+fn digits(s:&str) -> Option<(f64,usize,Option<usize>)> {
+    let bytes = s.as_bytes();
+    let mut len = 0;
+    let mut text = String::new();
+    let mut plain = true;
+    while len<bytes.len() {
+        if bytes[len].is_ascii_digit() { text.push(bytes[len] as char); len+=1; }
+        else if bytes[len]==b',' && len>0 && bytes.get(len+1..len+4).map(|group|group.iter().all(u8::is_ascii_digit)).unwrap_or(false) && !bytes.get(len+4).map(u8::is_ascii_digit).unwrap_or(false) { plain=false; len+=1; }
+        else { break; }
+    }
+    if len==0 { return None; }
+    if bytes.get(len)==Some(&b'.') && bytes.get(len+1).map(u8::is_ascii_digit).unwrap_or(false) {
+        plain=false;
+        text.push('.');
+        len+=1;
+        while len<bytes.len() && bytes[len].is_ascii_digit() { text.push(bytes[len] as char); len+=1; }
+    }
+    Some((text.parse().ok()?,len,if plain { Some(len) } else { None }))
+}
+
+/// A number in words, such as "five" or "twenty five".
+fn number_words(s:&str) -> Option<(f64,usize)> {
+    let (mut value,mut len) = word_from(s,&NUMBER_WORDS,false)?;
+    if value>=20.0 && value%10.0==0.0 {
+        let separator = if s[len..].starts_with(' ') || s[len..].starts_with('-') { 1 } else { 0 };
+        if separator==1 {
+            if let Some((units,units_len)) = word_from(&s[len+1..],&NUMBER_WORDS[1..10],false) { value+=units; len+=1+units_len; }
+        }
+    }
+    Some((value,len))
+}
+
+/// If s starts with a currency amount, number, percentage, year, date or legislation reference, return it and the length of text used.
+pub fn parse_typed(s:&str) -> Option<(TypedValue,usize)> {
+    if let Some(rest) = s.strip_prefix("bill ") {
+        let len = rest.find(|c:char|!(c.is_alphanumeric() || c=='-')).unwrap_or(rest.len());
+        let id = rest[..len].trim_end_matches('-');
+        if id.contains(|c:char|c.is_ascii_digit()) { return Some((TypedValue::Legislation(id.to_string()),5+id.len())); }
+        return None;
+    }
+    if let Some(res) = date(s) { return Some(res); }
+    let (currency,prefix_len) = match CURRENCY_PREFIXES.iter().find(|(symbol,_)|s.starts_with(symbol)) {
+        Some(&(symbol,currency)) => (Some(currency),symbol.len()),
+        None => (None,0),
+    };
+    let rest = &s[prefix_len..];
+    let (mut value,mut len,plain_digits,is_words) = if let Some((value,len,plain)) = digits(rest) { (value,len,plain,false) } else { let (value,len) = number_words(rest)?; (value,len,None,true) };
+    let mut scaled = false;
+    while let Some((scale,scale_len)) = word_from(&rest[len..],&SCALES,true) {
+        if rest[len..].starts_with(' ') && scale_len<=2 { break; } // "5 m" is more likely to be metres.
+        value*=scale;
+        len+=scale_len;
+        scaled=true;
+    }
+    if scaled { value=value.round(); }
+    let end = prefix_len+len;
+    if let Some(currency) = currency {
+        return if at_boundary(s,end) { Some((TypedValue::Money{currency:currency.to_string(),amount:value},end)) } else { None };
+    }
+    if let Some((currency,suffix_len)) = word_from(&s[end..],&CURRENCY_SUFFIXES,true) {
+        return Some((TypedValue::Money{currency:currency.to_string(),amount:value},end+suffix_len));
+    }
+    if s[end..].starts_with('%') { return Some((TypedValue::Percentage(value),end+1)); }
+    for percent in [" percent"," per cent"] {
+        if s[end..].starts_with(percent) && at_boundary(s,end+percent.len()) { return Some((TypedValue::Percentage(value),end+percent.len())); }
+    }
+    if is_words && !scaled { return None; }
+    if !at_boundary(s,end) { return None; }
+    if plain_digits==Some(4) && !scaled && (1800.0..=2100.0).contains(&value) {
+        let year = value as i32;
+        if let Some(to) = s[end..].strip_prefix('-').and_then(|r|digits(r).filter(|&(_,len,plain)|plain==Some(len) && (len==2 || len==4) && at_boundary(r,len))) {
+            let to_year = if to.1==2 { year/100*100+to.0 as i32 } else { to.0 as i32 };
+            if to_year>year { return Some((TypedValue::YearRange(year,to_year),end+1+to.1)); }
+        }
+        return Some((TypedValue::Year(year),end));
+    }
+    Some((TypedValue::Number(value),end))
+}
+
+/// A date written as "2021-03-04" or, Australian style, "4/3/2021".
+fn date(s:&str) -> Option<(TypedValue,usize)> {
+    let numbers : Vec<(u32,usize)> = {
+        let mut res = vec![];
+        let mut pos = 0;
+        let separator = if s.len()>4 && s.as_bytes()[4]==b'-' { '-' } else { '/' };
+        loop {
+            let (_,len,plain) = digits(&s[pos..])?;
+            plain?;
+            res.push((s[pos..pos+len].parse().ok()?,len));
+            pos+=len;
+            if res.len()==3 { break; }
+            if !s[pos..].starts_with(separator) { return None; }
+            pos+=1;
+        }
+        if !at_boundary(s,pos) { return None; }
+        res
+    };
+    let len = numbers.iter().map(|n|n.1).sum::<usize>()+2;
+    let (year,month,day) = if numbers[0].1==4 { (numbers[0].0,numbers[1].0,numbers[2].0) } else if numbers[2].1==4 { (numbers[2].0,numbers[1].0,numbers[0].0) } else { return None };
+    if (1..=12).contains(&month) && (1..=31).contains(&day) { Some((TypedValue::Date{year:year as i32,month,day},len)) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s:&str) -> Option<(String,usize)> { parse_typed(s).map(|(value,len)|(value.key(),len)) }
+
+    #[test]
+    fn values() {
+        let five_billion = Some(("money:$:5000000000".to_string(),3));
+        assert_eq!(parse("$5b for"),five_billion);
+        assert_eq!(parse("$5 billion for").map(|r|r.0),five_billion.clone().map(|r|r.0));
+        assert_eq!(parse("five billion dollars for").map(|r|r.0),five_billion.map(|r|r.0));
+        assert_eq!(parse("a$5,000,000"),Some(("money:$:5000000".to_string(),11)));
+        assert_eq!(parse("3.5% more"),Some(("percent:3.5".to_string(),4)));
+        assert_eq!(parse("twenty five per cent"),Some(("percent:25".to_string(),20)));
+        assert_eq!(parse("2021-22 budget"),Some(("years:2021-2022".to_string(),7)));
+        assert_eq!(parse("2021 budget"),Some(("year:2021".to_string(),4)));
+        assert_eq!(parse("2021-03-04"),Some(("date:2021-03-04".to_string(),10)));
+        assert_eq!(parse("4/3/2021."),Some(("date:2021-03-04".to_string(),8)));
+        assert_eq!(parse("bill c-12 passed"),Some(("bill:c-12".to_string(),9)));
+        assert_eq!(parse("17 people"),Some(("number:17".to_string(),2)));
+        assert_eq!(parse("1.1 million"),Some(("number:1100000".to_string(),11)));
+        assert_eq!(parse("one nation"),None);
+        assert_eq!(parse("bill shorten"),None);
+        assert_eq!(parse("5g"),None);
+        assert_eq!(parse("covid-19"),None);
+        assert_eq!(parse("1,00"),Some(("number:1".to_string(),1)));
+        assert_eq!(parse("$1,50"),Some(("money:$:1".to_string(),2)));
+    }
+}
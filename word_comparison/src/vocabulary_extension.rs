@@ -0,0 +1,186 @@
+//! Add new words (jargon like "robodebt") to an existing general vocabulary without rebuilding it.
+//!
+//! New words are added at the end, so existing [WordIndex] values are unchanged and anything
+//! indexed by them (such as a question database) stays valid. A new word gets synonyms either
+//! from its vector, compared against the vectors the file was made from (or the vectors stored in the file), or from a hand made list.
+//! New words are also added as synonyms of their synonyms, if they are good enough to make the list.
+
+use std::path::Path;
+use csv::ReaderBuilder;
+use rayon::prelude::*;
+use crate::near_words::{SmallestN, WordAndValue};
+use crate::word::{MemoryWords, WordIndex, WordSource, WordVec, WordVecs};
+use crate::word_file::WordFileContents;
+
+/// Where a new word's synonyms come from.
+pub enum NewWordSynonyms {
+    /// The word's vector, in the same space as the vectors the file was made from. Synonyms are the nearest words.
+    Vector(WordVec),
+    /// Hand specified synonyms and their goodness scores.
+    Listed(Vec<(String,f32)>),
+}
+
+pub struct NewWord {
+    pub word : String,
+    pub synonyms : NewWordSynonyms,
+    /// Number of times it occurs, only used if the file has word counts.
+    pub count : u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ExtensionReport {
+    /// Number of words added.
+    pub added : usize,
+    /// New words that were already in the vocabulary, and so were ignored.
+    pub already_present : Vec<String>,
+    /// Listed synonyms that are not in the vocabulary, and so were ignored.
+    pub unknown_synonyms : Vec<String>,
+}
+
+/// New words for each of the words with vectors, such as those read by [read_glove](crate::word::read_glove).
+pub fn new_words_from_vectors(words:&MemoryWords,wordvecs:&WordVecs) -> Vec<NewWord> {
+    words.all_indices().map(|i|NewWord{ word: words.word(i).to_string(), synonyms: NewWordSynonyms::Vector(wordvecs.get(i).clone()), count: 0 }).collect()
+}
+
+/// Read new words with hand specified synonyms from a csv file, with lines like `robodebt,centrelink,0.8,debt,0.6` being
+/// the new word followed by pairs of a synonym and its goodness score. The words are as written, and are normalized by [extend_vocabulary].
+pub fn read_listed_new_words<P:AsRef<Path>>(path:P) -> std::io::Result<Vec<NewWord>> {
+    let mut res = vec![];
+    let mut reader = ReaderBuilder::new().flexible(true).has_headers(false).comment(Some(b'#')).trim(csv::Trim::All).from_path(path)?;
+    for result in reader.records() {
+        let record = result?;
+        let line = record.position().map(|p|p.line()).unwrap_or(0);
+        if record.len()%2!=1 { return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,format!("New words line {} should be a word followed by pairs of synonym and score",line))); }
+        let mut synonyms = vec![];
+        for i in (1..record.len()).step_by(2) {
+            let score : f32 = record[i+1].parse().ok().filter(|score:&f32|score.is_finite()).ok_or_else(||std::io::Error::new(std::io::ErrorKind::InvalidData,format!("New words line {} has invalid score {}",line,&record[i+1])))?;
+            synonyms.push((record[i].to_string(),score));
+        }
+        res.push(NewWord{ word: record[0].to_string(), synonyms: NewWordSynonyms::Listed(synonyms), count: 0 });
+    }
+    Ok(res)
+}
+
+/// Add synonym to list, if there is room or it is better than the worst one.
+fn insert_synonym(list:&mut Vec<WordAndValue>,synonym:WordAndValue,num_synonyms:usize) {
+    if list.iter().any(|s|s.word==synonym.word) { return; }
+    if list.len()>=num_synonyms {
+        if list.last().map(|worst|worst.value>=synonym.value).unwrap_or(true) { return; }
+        list.pop();
+    }
+    let position = list.iter().position(|s|s.value<synonym.value).unwrap_or(list.len());
+    list.insert(position,synonym);
+}
+
+/// Add the new words to the end of the contents.
+///
+/// existing_vectors should be the vectors the file was made from (matched up by word), and are needed to find synonyms for new words with vectors
+/// unless the file has vectors stored in it. If the file has vectors stored in it, the new words' vectors are stored too (all zero for listed words).
+/// num_synonyms and min_cosine are as in [WordFileOptions](crate::word_file::WordFileOptions).
+pub fn extend_vocabulary(contents:&mut WordFileContents,existing_vectors:Option<(&MemoryWords,&WordVecs)>,new_words:Vec<NewWord>,num_synonyms:usize,min_cosine:Option<f32>) -> ExtensionReport {
+    let mut report = ExtensionReport::default();
+    let mut added : Vec<(WordIndex,NewWordSynonyms)> = vec![];
+    for mut new_word in new_words {
+        new_word.word = contents.normalization.normalize(&new_word.word);
+        if let NewWordSynonyms::Listed(listed) = &mut new_word.synonyms {
+            for (word,_) in listed.iter_mut() { *word = contents.normalization.normalize(word); }
+        }
+        if contents.words.index(&new_word.word).is_some() { report.already_present.push(new_word.word); continue; }
+        let index = contents.words.add(&new_word.word);
+        contents.synonyms.push(vec![]);
+        if let Some(counts) = &mut contents.word_counts { counts.push(new_word.count); }
+        if let Some(lemmas) = &mut contents.lemmas { lemmas.push(index); }
+        added.push((index,new_word.synonyms));
+        report.added+=1;
+    }
+    // The vector of each word, if known.
+    let stored = contents.vectors.as_ref().map(|(_,vectors)|vectors);
+    let mut vectors : Vec<Option<&WordVec>> = contents.words.all_indices().map(|i|match existing_vectors {
+        Some((words,wordvecs)) => words.index(contents.words.word(i)).map(|v|wordvecs.get(v)),
+        None => stored.filter(|stored|(i.0 as usize)<stored.len()).map(|stored|stored.get(i)),
+    }).collect();
+    for (index,synonyms) in &added {
+        if let NewWordSynonyms::Vector(vec) = synonyms { vectors[index.0 as usize]=Some(vec); }
+    }
+    let found : Vec<(WordIndex,Vec<WordAndValue>)> = added.par_iter().map(|(index,synonyms)|{
+        let list = match synonyms {
+            NewWordSynonyms::Vector(vec) => {
+                let mut best_cosine = SmallestN::new(num_synonyms);
+                for (i,other) in vectors.iter().enumerate() {
+                    if let Some(other) = other {
+                        if i!=index.0 as usize { best_cosine.add(WordAndValue{ word: WordIndex(i as u32), value: -vec.cosine(other) as f32 }); }
+                    }
+                }
+                best_cosine.into_sorted_vec().into_iter().map(|w|WordAndValue{word:w.word,value:-w.value}).filter(|w|min_cosine.map(|min|w.value>=min).unwrap_or(true)).collect()
+            }
+            NewWordSynonyms::Listed(listed) => {
+                let mut list : Vec<WordAndValue> = listed.iter().filter_map(|(word,value)|contents.words.index(word).map(|word|WordAndValue{word,value:*value})).collect();
+                list.sort_by(|a,b|b.value.total_cmp(&a.value));
+                list
+            }
+        };
+        (*index,list)
+    }).collect();
+    for (_,synonyms) in &added {
+        if let NewWordSynonyms::Listed(listed) = synonyms {
+            for (word,_) in listed {
+                if contents.words.index(word).is_none() && !report.unknown_synonyms.contains(word) { report.unknown_synonyms.push(word.clone()); }
+            }
+        }
+    }
+    for (index,list) in &found {
+        contents.synonyms[index.0 as usize]=list.clone();
+    }
+    if let Some((_,stored)) = &mut contents.vectors {
+        let dimensions = if stored.is_empty() { 0 } else { stored.get(WordIndex(0)).values().len() };
+        for (_,synonyms) in &added {
+            stored.push(match synonyms {
+                NewWordSynonyms::Vector(vec) => vec.clone(),
+                NewWordSynonyms::Listed(_) => WordVec::new(vec![0.0;dimensions]),
+            });
+        }
+    }
+    for (index,list) in found {
+        for synonym in list {
+            insert_synonym(&mut contents.synonyms[synonym.word.0 as usize],WordAndValue{word:index,value:synonym.value},num_synonyms);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word_file::WordsInFile;
+    use crate::test_util::{small_glove, small_word_file, temp_path};
+
+    #[test]
+    fn existing_indices_unchanged() {
+        let original = WordsInFile::read_word_file(small_word_file("extension")).unwrap();
+        let mut contents = WordFileContents::from_word_file(&original);
+        let (words,wordvecs) = small_glove("extension");
+        let new_words = vec![
+            NewWord{ word:"puppy".to_string(), synonyms:NewWordSynonyms::Vector(WordVec::new(vec![0.0,0.05,1.0])), count:0 },
+            NewWord{ word:"robodebt".to_string(), synonyms:NewWordSynonyms::Listed(vec![("Vaccine".to_string(),0.3),("nonsense".to_string(),0.9)]), count:0 },
+            NewWord{ word:"cat".to_string(), synonyms:NewWordSynonyms::Listed(vec![]), count:0 },
+        ];
+        let report = extend_vocabulary(&mut contents,Some((&words,&wordvecs)),new_words,3,None);
+        assert_eq!(report.added,2);
+        assert_eq!(report.already_present,vec!["cat".to_string()]);
+        assert_eq!(report.unknown_synonyms,vec!["nonsense".to_string()]);
+        let path = temp_path("extended.bin");
+        contents.write(&path).unwrap();
+        let extended = WordsInFile::read_word_file(&path).unwrap();
+        for i in original.all_indices() { assert_eq!(extended.index(original.word(i)),Some(i)); }
+        let puppy = extended.index("puppy").unwrap();
+        assert_eq!(puppy,WordIndex(9));
+        assert!(extended.synonyms(puppy).iter().any(|s|s.word==WordIndex(4))); // dog
+        assert!(extended.synonyms(WordIndex(4)).iter().any(|s|s.word==puppy));
+        assert_eq!(extended.synonyms(WordIndex(10))[0].word,WordIndex(7));
+        let listed = temp_path("extension_listed.csv");
+        std::fs::write(&listed,"robodebt,centrelink,0.8,debt,0.6\n").unwrap();
+        assert_eq!(read_listed_new_words(&listed).unwrap().len(),1);
+        std::fs::write(&listed,"robodebt,centrelink,nan\n").unwrap();
+        assert!(read_listed_new_words(&listed).is_err());
+    }
+}
@@ -4,6 +4,8 @@ use std::fs::File;
 use std::io::BufRead;
 use std::ops::Range;
 use std::iter::Map;
+use crate::embedding_reader::{read_embedding_file, EmbeddingFormat, EmbeddingOptions};
+use crate::normalize::Normalization;
 
 /// Words are represented by a lookup table. This is an index into that table. More common words are "lesser" by the Ord trait.
 #[derive(Eq, PartialEq,Debug,Ord, PartialOrd,Copy, Clone,Hash)]
@@ -12,6 +14,8 @@ pub struct WordIndex(pub u32);
 pub trait WordSource {
     /// The number of words
     fn len(&self) -> usize;
+    /// Whether there are no words
+    fn is_empty(&self) -> bool { self.len()==0 }
     /// The text of the word
     fn word(&self,index:WordIndex) -> &str ;
     /// The index of the word. Smaller values are more common.
@@ -23,6 +27,7 @@ pub trait WordSource {
 }
 
 
+#[derive(Default)]
 pub struct MemoryWords {
     words : Vec<String>,
     lookup : HashMap<String,WordIndex>,
@@ -36,7 +41,8 @@ impl WordSource for MemoryWords {
 }
 impl MemoryWords {
 
-    fn add(&mut self,s:&str) -> WordIndex {
+    /// Add a word to the end of the list, returning its index.
+    pub fn add(&mut self,s:&str) -> WordIndex {
         let res = WordIndex(self.words.len() as u32);
         self.words.push(s.to_string());
         self.lookup.insert(s.to_string(),res);
@@ -44,6 +50,7 @@ impl MemoryWords {
     }
 }
 
+#[derive(Clone)]
 pub struct WordVec {
     vec : Vec<f64>,
     mag : f64
@@ -71,12 +78,16 @@ fn distance(v1:&[f64],v2:&[f64]) -> f64 {
 impl WordVec {
     //fn len(&self) -> usize { self.vec.len() }
 
+    /// The components of the vector.
+    pub fn values(&self) -> &[f64] { &self.vec }
+
     pub fn dot_product(&self,v2:&WordVec) -> f64 {
         dot_product(&self.vec,&v2.vec)
     }
 
+    /// The cosine of the angle between the vectors, or 0 if either is all zeros.
     pub fn cosine(&self,v2:&WordVec) -> f64 {
-        self.dot_product(v2)/(self.mag*v2.mag)
+        if self.mag==0.0 || v2.mag==0.0 { 0.0 } else { self.dot_product(v2)/(self.mag*v2.mag) }
     }
 
     pub fn distance(&self,v2:&WordVec) -> f64 {
@@ -91,29 +102,36 @@ impl WordVec {
 
 
 /// same indices as Words.
+#[derive(Default)]
 pub struct WordVecs {
     vecs : Vec<WordVec>,
 }
 
 impl WordVecs {
     pub fn get(&self,word:WordIndex) -> &WordVec { &self.vecs[word.0 as usize] }
+    pub fn len(&self) -> usize { self.vecs.len() }
+    /// Add a vector for the next word.
+    pub fn push(&mut self,vec:WordVec) { self.vecs.push(vec) }
+    pub fn is_empty(&self) -> bool { self.vecs.is_empty() }
 }
 
-/// Read a glove format file, up to max_words if not None.
+/// Read a glove format file, up to max_words if not None. See [crate::embedding_reader] for other formats.
 pub fn read_glove<P:AsRef<Path>>(path:P,max_words:Option<usize>) -> std::io::Result<(MemoryWords, WordVecs)> {
+    read_embedding_file(path,EmbeddingFormat::Glove,&EmbeddingOptions{max_words,cased:false,normalization:None})
+}
+
+/// Read a file of word counts, one word per line, the word followed by whitespace followed by the count, such as the vocab.txt
+/// file produced by GloVe. Returns the count for each word in words, 0 if not in the file.
+/// The words in the file are normalized in the same way as words, and counts of words that normalize to the same word are added.
+pub fn read_word_counts<P:AsRef<Path>,W:WordSource>(path:P,words:&W,normalization:Normalization) -> std::io::Result<Vec<u64>> {
     let file = File::open(path)?;
-    let mut words = MemoryWords { words: vec![], lookup: Default::default() };
-    let mut wordvecs=WordVecs{ vecs: vec![] };
-    for line in std::io::BufReader::new(file).lines() {
+    let mut counts = vec![0;words.len()];
+    for (line_number,line) in std::io::BufReader::new(file).lines().enumerate() {
         let line = line?;
-        let line = line.split(' ').collect::<Vec<_>>();
-        let word = line[0];
-        words.add(word);
-        let nums : Vec<f64> = line[1..].iter().map(|l|l.parse().unwrap()).collect();
-        wordvecs.vecs.push(WordVec::new(nums));
-        if let Some(max) = max_words {
-            if words.len()==max { break; }
-        }
+        if line.trim().is_empty() { continue; }
+        let (word,count) = line.rsplit_once(|c:char|c.is_whitespace()).ok_or_else(||std::io::Error::new(std::io::ErrorKind::InvalidData,format!("Line {} of word counts has no count",line_number+1)))?;
+        let count : u64 = count.parse().map_err(|_|std::io::Error::new(std::io::ErrorKind::InvalidData,format!("Line {} of word counts has an invalid count {}",line_number+1,count)))?;
+        if let Some(index) = words.index(&normalization.normalize(word.trim_end())) { counts[index.0 as usize]+=count; }
     }
-    Ok((words,wordvecs))
-}
\ No newline at end of file
+    Ok(counts)
+}
@@ -1,160 +1,827 @@
-//! Store words in a file in a way that can be looked up quickly with mmap.
+//! Store words in a file in a way that can be looked up quickly with mmap, or loaded into memory.
 
 
 
-use crate::word::{MemoryWords, WordVecs, WordIndex, WordSource};
+use crate::word::{MemoryWords, WordVec, WordVecs, WordIndex, WordSource};
 use std::path::Path;
 use std::fs::File;
 use std::io::Write;
-use crate::near_words::{SmallestN, WordAndValue};
+use crate::near_words::WordAndValue;
+use crate::synonym_search::{find_synonyms, measure_recall, RecallReport, SynonymSearch};
+use crate::synonym_overlay::{ResolvedSynonymOverlay, SynonymOverlay};
+use crate::lemmas::Lemmas;
+use crate::normalize::Normalization;
 use memmap::Mmap;
 use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::convert::TryInto;
 
 pub const WORD_MMAP_FILE : &str = "GeneralVocabulary.bin";
 
+/// The format version written by [write_word_file]. Files from before format versions existed are reported as version 0.
+pub const WORD_FILE_FORMAT_VERSION : u32 = 2;
 
-/// * File format
+/// In the original unversioned format, the number of words came straight after the magic number. No file can have this many words, so it marks a versioned file.
+const VERSIONED_MARKER : u32 = u32::MAX;
+/// Length of "WORD", the versioned marker, the format version and the checksum.
+const HEADER_LENGTH : usize = 16;
+/// Length of a section tag, offset and length in the section table.
+const SECTION_TABLE_ENTRY_LENGTH : usize = 20;
+
+/// A 4 byte ASCII identifier of a section of the file.
+pub type SectionTag = [u8;4];
+pub const SYNONYMS_SECTION : SectionTag = *b"SYNS";
+pub const VARIABLE_SYNONYMS_SECTION : SectionTag = *b"SYNV";
+pub const ALPHABETICAL_SECTION : SectionTag = *b"ALPH";
+pub const OFFSETS_SECTION : SectionTag = *b"OFFS";
+pub const STRINGS_SECTION : SectionTag = *b"STRS";
+pub const FREQUENCY_SECTION : SectionTag = *b"FREQ";
+pub const VECTORS_SECTION : SectionTag = *b"VECS";
+pub const LEMMAS_SECTION : SectionTag = *b"LEMM";
+pub const PREFIX_SECTION : SectionTag = *b"PRFX";
+pub const NORMALIZATION_SECTION : SectionTag = *b"NORM";
+
+/// How word vectors are stored in the "VECS" section. Vectors are only needed for similarity between arbitrary words, so precision is traded for size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VectorQuantization {
+    /// Each component is a half precision float. Half the size of f32, and practically as accurate.
+    F16,
+    /// Each vector has an f32 scale, and each component is a signed byte multiplied by the scale. A quarter the size of f32.
+    Int8,
+}
+
+impl VectorQuantization {
+    /// The code stored in the file.
+    fn code(self) -> u32 {
+        match self {
+            VectorQuantization::F16 => 1,
+            VectorQuantization::Int8 => 2,
+        }
+    }
+    fn from_code(code:u32) -> Option<Self> {
+        match code {
+            1 => Some(VectorQuantization::F16),
+            2 => Some(VectorQuantization::Int8),
+            _ => None,
+        }
+    }
+    /// The number of bytes used to store one vector.
+    fn bytes_per_vector(self,dimensions:usize) -> Option<usize> {
+        match self {
+            VectorQuantization::F16 => dimensions.checked_mul(2),
+            VectorQuantization::Int8 => dimensions.checked_add(4),
+        }
+    }
+}
+
+/// # File format
 /// All things are little endian.
 /// Word identifiers are 4 bytes.
 ///
 /// 4 Bytes : Ascii "WORD"
+/// 4 bytes : 0xffffffff, marking this as a versioned file (unversioned files have the number of words here).
+/// 4 bytes : Format version, [WORD_FILE_FORMAT_VERSION].
+/// 4 bytes : CRC32 checksum of everything after this field.
 /// 4 bytes : Number of words (N).
-/// 4 bytes : Number of synonyms (n).
-/// N * (n * (4+4bytes)) : for each word i, the n best synonyms, in order best to worst, each one being a word identifer (4 bytes) and a correlation score (f32 4 bytes)
-/// N * 4bytes : Word identifier i, in alphabetical order.
-/// N * 4bytes : Offset of word i, relative to after this section.
-/// 4 bytes : Length of this section (N strings) in bytes
-/// base if where offset of word i is relative to
-/// N * utf-8 nul terminated strings, being words referred to above.
+/// 4 bytes : Number of sections (S).
+/// S * (4+8+8 bytes) : for each section, its ASCII tag, its offset from the start of the file, and its length in bytes.
+///
+/// The sections are
+/// * "SYNV" : (N+1) * 4 bytes : for each word i, the number of synonyms of words before i (M for i=N), then
+///   M * (4+4bytes) : for each word i, its synonyms, in order best to worst, each one being a word identifer (4 bytes) and a correlation score (f32 4 bytes)
+/// * "SYNS" : Only in version 1, instead of "SYNV". 4 bytes number of synonyms (n), then N * (n * (4+4bytes)) : for each word i, the n best synonyms, in the same format as "SYNV".
+/// * "ALPH" : N * 4bytes : Word identifier i, in alphabetical order.
+/// * "OFFS" : N * 4bytes : Offset of word i, relative to the start of the "STRS" section.
+/// * "STRS" : N * utf-8 nul terminated strings, being words referred to above.
+/// * "FREQ" (optional) : 8 bytes total of the counts, then N * 8 bytes : the number of times word i occurs in some corpus.
+/// * "VECS" (optional) : 4 bytes number of dimensions (D), 4 bytes [VectorQuantization] (1 for F16, 2 for Int8), then for each word i, its vector.
+///   For F16 this is D * 2 bytes, each a half precision float. For Int8 this is an f32 scale followed by D signed bytes, each component being the byte times the scale.
+/// * "LEMM" (optional) : N * 4 bytes : the word identifier of the lemma of word i (i itself if it has no other lemma).
+/// * "PRFX" : A finite state transducer (in the format of the [fst] crate) mapping each word to its identifier, used to find the longest word at the start of some text.
+///   Optional when reading, as it can be rebuilt from "ALPH".
+/// * "NORM" : 4 bytes : the [Normalization] the words were made with (bit 0 set if accents were stripped). Optional when reading, the default being [Normalization::default].
+///
+/// Unversioned files (version 0) are the same as "WORD", N, n, then the contents of the "SYNS" (without n), "ALPH" and "OFFS" sections,
+/// then 4 bytes length of the "STRS" section, then the "STRS" section.
 pub fn write_word_file<P:AsRef<Path>>(path:P,words:&MemoryWords,wordvecs:&WordVecs,num_synonyms:u32) -> std::io::Result<()>{
-    let mut file = File::create(path)?;
-    file.write_all("WORD".as_bytes())?;
-    file.write_all(&(words.len() as u32).to_le_bytes())?;
-    file.write_all(&num_synonyms.to_le_bytes())?;
-    for word_index in words.all_indices() {
-        let vec_i = wordvecs.get(word_index);
-        let mut best_cosine = SmallestN::new(num_synonyms as usize);
-        for comparison in words.all_indices() {
-            if comparison!=word_index {
-                best_cosine.add(WordAndValue{ word: comparison, value: -vec_i.cosine(wordvecs.get(comparison)) as f32 });
-            }
-        }
-        let best_cosine = best_cosine.into_sorted_vec();
-        for synonym in best_cosine {
-            file.write_all(&(synonym.word.0 as u32).to_le_bytes())?;
-            file.write_all(&(-synonym.value as f32).to_le_bytes())?;
+    write_word_file_with_options(path,words,wordvecs,&WordFileOptions{num_synonyms,..Default::default()})?;
+    Ok(())
+}
+
+/// Choices about how to make the word file.
+#[derive(Clone, Debug)]
+pub struct WordFileOptions {
+    /// The maximum number of synonyms stored for each word.
+    pub num_synonyms : u32,
+    /// If present, synonyms with a lower cosine than this are not stored.
+    pub min_cosine : Option<f32>,
+    /// How to find the synonyms.
+    pub search : SynonymSearch,
+    /// If not zero and the search is approximate, compare this many words' synonyms against exact search.
+    pub recall_sample_size : usize,
+    /// The number of threads to use. None means one per core.
+    pub threads : Option<usize>,
+    /// If present, for each word, the number of times it occurs in some corpus. See [read_word_counts](crate::word::read_word_counts).
+    pub word_counts : Option<Vec<u64>>,
+    /// If present, hand made corrections to the synonyms. These are applied after min_cosine and num_synonyms, so added synonyms are always kept.
+    pub synonym_overlay : Option<SynonymOverlay>,
+    /// If present, store the word vectors in the file in this form, so that any two words can be compared later.
+    pub vectors : Option<VectorQuantization>,
+    /// If present, store the lemma of each word in the file.
+    pub lemmas : Option<Lemmas>,
+    /// The normalization the words were made with, which will be used for text looked up in the file.
+    pub normalization : Normalization,
+}
+
+impl Default for WordFileOptions {
+    fn default() -> Self {
+        WordFileOptions{ num_synonyms: 20, min_cosine: None, search: SynonymSearch::Exact, recall_sample_size: 0, threads: None, word_counts: None, synonym_overlay: None, vectors: None, lemmas: None, normalization: Normalization::default() }
+    }
+}
+
+/// Information about how making the word file went.
+#[derive(Clone, Debug)]
+pub struct WordFileReport {
+    /// Time spent finding synonyms.
+    pub synonym_search_time : std::time::Duration,
+    /// Comparison of approximate search with exact search, if requested.
+    pub recall : Option<RecallReport>,
+    /// Words in the synonym overlay that are not in the vocabulary, and so were ignored.
+    pub unknown_overlay_words : Vec<String>,
+    /// The number of (word,lemma) pairs ignored as one of the words is not in the vocabulary.
+    pub ignored_lemmas : usize,
+}
+
+/// Like [write_word_file], but with more choices. See [write_word_file] for the file format.
+pub fn write_word_file_with_options<P:AsRef<Path>>(path:P,words:&MemoryWords,wordvecs:&WordVecs,options:&WordFileOptions) -> std::io::Result<WordFileReport>{
+    let num_synonyms = options.num_synonyms as usize;
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(options.threads.unwrap_or(0)).build().map_err(std::io::Error::other)?;
+    let start_time = std::time::Instant::now();
+    let found = pool.install(||find_synonyms(wordvecs,num_synonyms,&options.search));
+    let synonym_search_time = start_time.elapsed();
+    let recall = match options.search {
+        SynonymSearch::Approximate(_) if options.recall_sample_size>0 => Some(pool.install(||measure_recall(wordvecs,&found,num_synonyms,options.recall_sample_size,1))),
+        _ => None,
+    };
+    let found : Vec<Vec<WordAndValue>> = match options.min_cosine {
+        Some(min_cosine) => found.into_iter().map(|best|best.into_iter().filter(|s|s.value>=min_cosine).collect()).collect(),
+        None => found,
+    };
+    let mut unknown_overlay_words = vec![];
+    let found : Vec<Vec<WordAndValue>> = match &options.synonym_overlay {
+        Some(overlay) => {
+            let overlay = overlay.resolve(words,options.normalization);
+            unknown_overlay_words = overlay.unknown_words.clone();
+            found.into_iter().enumerate().map(|(i,best)|overlay.apply(WordIndex(i as u32),best)).collect()
         }
+        None => found,
+    };
+    let (lemmas,ignored_lemmas) = match &options.lemmas {
+        Some(lemmas) => { let (table,ignored) = lemmas.resolve(words,options.normalization); (Some(table),ignored) }
+        None => (None,0),
+    };
+    write_contents(path,words,&found,options.word_counts.as_deref(),options.vectors.map(|quantization|(quantization,wordvecs)),lemmas.as_deref(),options.normalization)?;
+    Ok(WordFileReport{ synonym_search_time, recall, unknown_overlay_words, ignored_lemmas })
+}
+
+/// Everything stored in a word file, in memory. Used to modify an existing word file.
+pub struct WordFileContents {
+    pub words : MemoryWords,
+    /// For each word, its synonyms, best first.
+    pub synonyms : Vec<Vec<WordAndValue>>,
+    /// If present, for each word, the number of times it occurs in some corpus.
+    pub word_counts : Option<Vec<u64>>,
+    /// If present, the vector for each word (as read from the file, so already quantized) and how to store them.
+    pub vectors : Option<(VectorQuantization,WordVecs)>,
+    /// If present, the lemma of each word.
+    pub lemmas : Option<Vec<WordIndex>>,
+    pub normalization : Normalization,
+}
+
+impl WordFileContents {
+    /// Get the contents of an existing word file. Synonyms will include any overlay applied to it.
+    pub fn from_word_file(file:&WordsInFile) -> Self {
+        let mut words = MemoryWords::default();
+        for i in file.all_indices() { words.add(file.word(i)); }
+        let synonyms = file.all_indices().map(|i|file.synonyms(i)).collect();
+        let word_counts = file.total_frequency().map(|_|file.all_indices().map(|i|file.frequency(i).unwrap()).collect());
+        let vectors = file.vector_quantization().map(|quantization|{
+            let mut vectors = WordVecs::default();
+            for i in file.all_indices() { vectors.push(file.vector(i).unwrap()); }
+            (quantization,vectors)
+        });
+        let lemmas = if file.has_lemmas() { Some(file.all_indices().map(|i|file.lemma(i)).collect()) } else { None };
+        WordFileContents{ words, synonyms, word_counts, vectors, lemmas, normalization:file.normalization() }
+    }
+
+    /// Write in the format described in [write_word_file].
+    pub fn write<P:AsRef<Path>>(&self,path:P) -> std::io::Result<()> {
+        write_contents(path,&self.words,&self.synonyms,self.word_counts.as_deref(),self.vectors.as_ref().map(|(quantization,vectors)|(*quantization,vectors)),self.lemmas.as_deref(),self.normalization)
     }
+}
+
+/// Write a word file, in the format described in [write_word_file].
+/// Fails with [std::io::ErrorKind::InvalidInput] if there is not one of each thing given per word.
+fn write_contents<P:AsRef<Path>,W:WordSource>(path:P,words:&W,synonyms:&[Vec<WordAndValue>],word_counts:Option<&[u64]>,vectors:Option<(VectorQuantization,&WordVecs)>,lemmas:Option<&[WordIndex]>,normalization:Normalization) -> std::io::Result<()> {
+    let check_length = |what:&str,found:usize| if found==words.len() { Ok(()) } else {
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,format!("Need one {} per word, but there are {} for {} words",what,found,words.len())))
+    };
+    check_length("list of synonyms",synonyms.len())?;
+    if let Some(counts) = word_counts { check_length("word count",counts.len())?; }
+    if let Some((_,wordvecs)) = vectors { check_length("vector",wordvecs.len())?; }
+    if let Some(lemmas) = lemmas { check_length("lemma",lemmas.len())?; }
+    let synonyms = variable_synonyms_section(synonyms);
+    let mut alphabetical : Vec<u8> = vec![];
     let mut alphabetical_order : Vec<WordIndex> = words.all_indices().collect();
     alphabetical_order.sort_by_key(|w|words.word(*w));
-    for w in alphabetical_order {
-        file.write_all(&(w.0 as u32).to_le_bytes())?;
+    for w in &alphabetical_order {
+        alphabetical.write_all(&w.0.to_le_bytes())?;
     }
+    let prefix = prefix_section(words,&alphabetical_order)?;
+    let mut offsets : Vec<u8> = vec![];
     let mut word_text: Vec<u8> = vec![];
     for word_index in words.all_indices() {
-        file.write_all(&(word_text.len() as u32).to_le_bytes())?;
+        offsets.write_all(&(word_text.len() as u32).to_le_bytes())?;
         word_text.write_all(words.word(word_index).as_bytes())?;
         word_text.write_all(&[0u8])?;
     }
-    file.write_all(&(word_text.len() as u32).to_le_bytes())?;
-    file.write_all(&word_text)?;
-    Ok(())
+    let mut sections : Vec<(SectionTag,&[u8])> = vec![(VARIABLE_SYNONYMS_SECTION,&synonyms),(ALPHABETICAL_SECTION,&alphabetical),(OFFSETS_SECTION,&offsets),(STRINGS_SECTION,&word_text),(PREFIX_SECTION,&prefix)];
+    let normalization = normalization.to_flags().to_le_bytes();
+    sections.push((NORMALIZATION_SECTION,&normalization));
+    let mut frequencies : Vec<u8> = vec![];
+    if let Some(counts) = word_counts {
+        frequencies.write_all(&counts.iter().sum::<u64>().to_le_bytes())?;
+        for count in counts { frequencies.write_all(&count.to_le_bytes())?; }
+        sections.push((FREQUENCY_SECTION,&frequencies));
+    }
+    let vectors = vectors.map(|(quantization,wordvecs)|vectors_section(wordvecs,quantization)).transpose()?;
+    if let Some(vectors) = &vectors { sections.push((VECTORS_SECTION,vectors)); }
+    let mut lemma_section : Vec<u8> = vec![];
+    if let Some(lemmas) = lemmas {
+        for lemma in lemmas { lemma_section.write_all(&lemma.0.to_le_bytes())?; }
+        sections.push((LEMMAS_SECTION,&lemma_section));
+    }
+    write_sections(path,words.len(),&sections)
 }
 
+/// The contents of the "PRFX" section, given the words in alphabetical order. If a word is repeated, the first one is used.
+fn prefix_section<W:WordSource>(words:&W,alphabetical_order:&[WordIndex]) -> std::io::Result<Vec<u8>> {
+    let mut builder = fst::MapBuilder::memory();
+    let mut previous : Option<&str> = None;
+    for &w in alphabetical_order {
+        let word = words.word(w);
+        if previous==Some(word) { continue; }
+        builder.insert(word,w.0 as u64).map_err(std::io::Error::other)?;
+        previous=Some(word);
+    }
+    builder.into_inner().map_err(std::io::Error::other)
+}
+
+/// The contents of the "VECS" section, or an [std::io::ErrorKind::InvalidInput] error if the vectors have different numbers of dimensions.
+fn vectors_section(wordvecs:&WordVecs,quantization:VectorQuantization) -> std::io::Result<Vec<u8>> {
+    let dimensions = if wordvecs.is_empty() { 0 } else { wordvecs.get(WordIndex(0)).values().len() };
+    let mut res : Vec<u8> = vec![];
+    res.extend_from_slice(&(dimensions as u32).to_le_bytes());
+    res.extend_from_slice(&quantization.code().to_le_bytes());
+    for i in 0..wordvecs.len() {
+        let values = wordvecs.get(WordIndex(i as u32)).values();
+        if values.len()!=dimensions { return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,format!("Vector {} has {} dimensions, but vector 0 has {}",i,values.len(),dimensions))); }
+        match quantization {
+            VectorQuantization::F16 => for v in values { res.extend_from_slice(&half::f16::from_f64(*v).to_le_bytes()); }
+            VectorQuantization::Int8 => {
+                let scale = values.iter().fold(0.0f64,|max,v|max.max(v.abs()))/127.0;
+                res.extend_from_slice(&(scale as f32).to_le_bytes());
+                for v in values { res.push(if scale==0.0 { 0 } else { (v/scale).round().clamp(-127.0,127.0) as i8 as u8 }); }
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// The contents of the "SYNV" section.
+fn variable_synonyms_section(synonyms:&[Vec<WordAndValue>]) -> Vec<u8> {
+    let mut res : Vec<u8> = vec![];
+    let mut count = 0;
+    for list in synonyms {
+        res.extend_from_slice(&(count as u32).to_le_bytes());
+        count+=list.len();
+    }
+    res.extend_from_slice(&(count as u32).to_le_bytes());
+    for synonym in synonyms.iter().flatten() {
+        res.extend_from_slice(&synonym.word.0.to_le_bytes());
+        res.extend_from_slice(&synonym.value.to_le_bytes());
+    }
+    res
+}
+
+/// Write the header, section table and sections, in the format described in [write_word_file].
+fn write_sections<P:AsRef<Path>>(path:P,number_words:usize,sections:&[(SectionTag,&[u8])]) -> std::io::Result<()> {
+    let mut table : Vec<u8> = vec![];
+    table.write_all(&(number_words as u32).to_le_bytes())?;
+    table.write_all(&(sections.len() as u32).to_le_bytes())?;
+    let mut offset = HEADER_LENGTH+8+sections.len()*SECTION_TABLE_ENTRY_LENGTH;
+    for (tag,contents) in sections {
+        table.write_all(tag)?;
+        table.write_all(&(offset as u64).to_le_bytes())?;
+        table.write_all(&(contents.len() as u64).to_le_bytes())?;
+        offset+=contents.len();
+    }
+    let mut checksum = crc32fast::Hasher::new();
+    checksum.update(&table);
+    for (_,contents) in sections { checksum.update(contents); }
+    let mut file = std::io::BufWriter::new(File::create(path)?);
+    file.write_all("WORD".as_bytes())?;
+    file.write_all(&VERSIONED_MARKER.to_le_bytes())?;
+    file.write_all(&WORD_FILE_FORMAT_VERSION.to_le_bytes())?;
+    file.write_all(&checksum.finalize().to_le_bytes())?;
+    file.write_all(&table)?;
+    for (_,contents) in sections { file.write_all(contents)?; }
+    file.flush()
+}
+
+/// The tag as text, e.g. "SYNV".
+pub fn tag_name(tag:&SectionTag) -> String { String::from_utf8_lossy(tag).to_string() }
+
+/// Something wrong with a word file found while loading it.
+#[derive(Debug)]
+pub enum WordFileError {
+    Io(std::io::Error),
+    /// The file does not start with "WORD"
+    BadMagic,
+    /// The file was written by a newer program.
+    UnsupportedVersion(u32),
+    /// The file is too short to contain the named thing.
+    Truncated{ what:String, needed:usize, file_length:usize },
+    ChecksumMismatch{ stored:u32, computed:u32 },
+    MissingSection(SectionTag),
+    DuplicateSection(SectionTag),
+    /// A section has a length inconsistent with the number of words.
+    SectionWrongLength{ section:SectionTag, expected:usize, found:usize },
+    /// The position'th word identifier in the section is not less than the number of words.
+    WordIndexOutOfRange{ section:SectionTag, position:usize, value:u32 },
+    /// A synonym score is not a finite number.
+    BadScore{ word:WordIndex, position:usize },
+    StringOffsetOutOfRange{ word:WordIndex, offset:u32 },
+    StringNotTerminated(WordIndex),
+    StringNotUtf8(WordIndex),
+    /// The table of where synonyms start decreases for this word.
+    SynonymTableNotIncreasing(WordIndex),
+    /// The alphabetical list is out of order or has a repeated word identifier at this position.
+    NotAlphabetical{ position:usize },
+    /// The "VECS" section uses a way of storing vectors that this program does not know.
+    UnknownVectorQuantization(u32),
+    /// The "PRFX" section is not a valid fst, or does not match the words.
+    BadPrefixIndex(String),
+}
+
+impl Display for WordFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WordFileError::Io(e) => write!(f,"{}",e),
+            WordFileError::BadMagic => write!(f,"Not a word file - does not start with WORD"),
+            WordFileError::UnsupportedVersion(version) => write!(f,"Word file format version {} is not supported, maximum supported is {}",version,WORD_FILE_FORMAT_VERSION),
+            WordFileError::Truncated { what, needed, file_length } => write!(f,"Word file truncated - {} needs {} bytes but the file is only {} bytes",what,needed,file_length),
+            WordFileError::ChecksumMismatch { stored, computed } => write!(f,"Word file checksum mismatch - stored {:08x} but computed {:08x}",stored,computed),
+            WordFileError::MissingSection(tag) => write!(f,"Word file is missing section {}",tag_name(tag)),
+            WordFileError::DuplicateSection(tag) => write!(f,"Word file has section {} more than once",tag_name(tag)),
+            WordFileError::SectionWrongLength { section, expected, found } => write!(f,"Word file section {} should be {} bytes but is {} bytes",tag_name(section),expected,found),
+            WordFileError::WordIndexOutOfRange { section, position, value } => write!(f,"Word file section {} entry {} refers to word {} which does not exist",tag_name(section),position,value),
+            WordFileError::BadScore { word, position } => write!(f,"Word file synonym {} of word {} has a score that is not a finite number",position,word.0),
+            WordFileError::StringOffsetOutOfRange { word, offset } => write!(f,"Word file text for word {} has offset {} past the end of the strings",word.0,offset),
+            WordFileError::StringNotTerminated(word) => write!(f,"Word file text for word {} is not nul terminated",word.0),
+            WordFileError::StringNotUtf8(word) => write!(f,"Word file text for word {} is not utf-8",word.0),
+            WordFileError::SynonymTableNotIncreasing(word) => write!(f,"Word file synonym table decreases at word {}",word.0),
+            WordFileError::NotAlphabetical { position } => write!(f,"Word file alphabetical list is out of order at position {}",position),
+            WordFileError::UnknownVectorQuantization(code) => write!(f,"Word file vectors are stored in unknown form {}",code),
+            WordFileError::BadPrefixIndex(problem) => write!(f,"Word file prefix index is bad : {}",problem),
+        }
+    }
+}
+
+impl std::error::Error for WordFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WordFileError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for WordFileError {
+    fn from(e: std::io::Error) -> Self { WordFileError::Io(e) }
+}
+
+impl From<WordFileError> for std::io::Error {
+    fn from(e: WordFileError) -> Self {
+        match e {
+            WordFileError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData,e),
+        }
+    }
+}
+
+/// The contents of a word file, either memory mapped or read into memory.
+enum WordFileData {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl std::ops::Deref for WordFileData {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            WordFileData::Mapped(mmap) => mmap,
+            WordFileData::Owned(data) => data,
+        }
+    }
+}
 
 pub struct WordsInFile {
-    mmap : Mmap,
+    data : WordFileData,
+    format_version : u32,
     number_words: usize,
+    /// The number of synonyms per word, if it is the same for every word (version 0 and 1 files).
     num_synonyms : usize,
+    /// The start of the table of where each word's synonyms start, if variable.
+    synonym_table_start : Option<usize>,
+    /// The start of the synonym entries.
     synonyms_start : usize,
     alphabetic_order_start : usize,
     offsets_start : usize,
     strings_start : usize,
+    strings_end : usize,
+    /// start of the "FREQ" section, if there is one.
+    frequency_start : Option<usize>,
+    /// where the vectors are, if there is a "VECS" section.
+    vectors : Option<VectorsLocation>,
+    /// start of the "LEMM" section, if there is one.
+    lemmas_start : Option<usize>,
+    /// Lemmas loaded separately, overriding any in the file.
+    loaded_lemmas : Option<Vec<WordIndex>>,
+    /// The tag and length of each section, in the order in the file. Empty for unversioned files.
+    section_lengths : Vec<(SectionTag,usize)>,
+    /// The start and end of the "PRFX" section, if there is one.
+    prefix_section : Option<(usize,usize)>,
+    /// The prefix index built when loading, if the file does not have one.
+    built_prefix_index : Vec<u8>,
+    normalization : Normalization,
+    /// Hand made corrections to the synonyms in the file.
+    synonym_overlay : Option<ResolvedSynonymOverlay>,
+}
+
+/// Where the vectors are in the file, and how they are stored.
+struct VectorsLocation {
+    /// Start of the first vector (after the dimensions and quantization).
+    start : usize,
+    dimensions : usize,
+    quantization : VectorQuantization,
+    bytes_per_vector : usize,
+}
+
+/// Read a little endian u32 at the given offset, or None if that would go past the end.
+fn read_u32_checked(data:&[u8],offset:usize) -> Option<u32> {
+    data.get(offset..offset.checked_add(4)?).map(|b|u32::from_le_bytes([b[0],b[1],b[2],b[3]]))
+}
+
+/// Compute start+count*size, or an error if it overflows or is past the end of the file.
+fn end_of(what:&str,start:usize,count:usize,size:usize,file_length:usize) -> Result<usize,WordFileError> {
+    let truncated = ||WordFileError::Truncated{what:what.to_string(),needed:usize::MAX,file_length};
+    let end = count.checked_mul(size).and_then(|len|len.checked_add(start)).ok_or_else(truncated)?;
+    if end>file_length { Err(WordFileError::Truncated{what:what.to_string(),needed:end,file_length}) } else { Ok(end) }
 }
 
 impl WordsInFile {
     /// Read the word file in a mmap mode - modifying the file while running will cause a crash!
-    pub fn read_word_file<P:AsRef<Path>>(path:P) -> std::io::Result<Self> {
-        let file = File::open(path)?;
+    /// Replacing it (writing a new file and renaming it over the old one) is safe, although the old contents will continue to be used.
+    ///
+    /// The file is fully checked before returning, so a truncated or corrupt file gives an error here rather than a panic later.
+    pub fn read_word_file<P:AsRef<Path>>(path:P) -> Result<Self,WordFileError> {
+        let file = File::open(path.as_ref())?;
         let mmap = unsafe { Mmap::map(&file)?  };
-        let four_array = |offset:usize| [mmap[offset],mmap[offset+1],mmap[offset+2],mmap[offset+3]];
-        let read_u32 = |offset:usize| u32::from_le_bytes(four_array(offset));
-        let num_words = read_u32(4) as usize;
-        let num_synonyms = read_u32(8) as usize;
-        let synonyms_start = 12;
-        let alphabetic_order_start = synonyms_start+num_words*num_synonyms*8;
-        let offsets_start = alphabetic_order_start+num_words*4;
-        let strings_start = offsets_start+num_words*4+4;
-        Ok(WordsInFile{
-            mmap,
-            number_words: num_words,
-            num_synonyms,
-            synonyms_start,
-            alphabetic_order_start,
-            offsets_start,
-            strings_start
-        })
-    }
-    fn four_array(&self,offset:usize) -> [u8;4] { [self.mmap[offset],self.mmap[offset+1],self.mmap[offset+2],self.mmap[offset+3]] }
+        Self::from_data(WordFileData::Mapped(mmap))
+    }
+
+    /// Read the word file into memory. This is slower to start and uses more memory than [WordsInFile::read_word_file],
+    /// but the file may be modified or replaced while running.
+    pub fn read_word_file_into_memory<P:AsRef<Path>>(path:P) -> Result<Self,WordFileError> {
+        let data = std::fs::read(path.as_ref())?;
+        Self::from_data(WordFileData::Owned(data))
+    }
+
+    /// Use the contents of a word file that are already in memory.
+    pub fn from_bytes(data:Vec<u8>) -> Result<Self,WordFileError> {
+        Self::from_data(WordFileData::Owned(data))
+    }
+
+    fn from_data(data:WordFileData) -> Result<Self,WordFileError> {
+        let file_length = data.len();
+        end_of("the header",0,1,12,file_length)?;
+        if &data[0..4]!=b"WORD" { return Err(WordFileError::BadMagic); }
+        let read_u32 = |offset:usize| read_u32_checked(&data,offset).unwrap(); // only used after checking length.
+        let mut res = if read_u32(4)==VERSIONED_MARKER {
+            end_of("the header",0,1,HEADER_LENGTH+8,file_length)?;
+            let format_version = read_u32(8);
+            if format_version==0 || format_version>WORD_FILE_FORMAT_VERSION { return Err(WordFileError::UnsupportedVersion(format_version)); }
+            let number_words = read_u32(HEADER_LENGTH) as usize;
+            let num_sections = read_u32(HEADER_LENGTH+4) as usize;
+            let table_start = HEADER_LENGTH+8;
+            end_of("the section table",table_start,num_sections,SECTION_TABLE_ENTRY_LENGTH,file_length)?;
+            let mut sections : Vec<(SectionTag,usize,usize)> = vec![];
+            for i in 0..num_sections {
+                let entry = &data[table_start+i*SECTION_TABLE_ENTRY_LENGTH..table_start+(i+1)*SECTION_TABLE_ENTRY_LENGTH];
+                let tag : SectionTag = [entry[0],entry[1],entry[2],entry[3]];
+                let start = u64::from_le_bytes(entry[4..12].try_into().unwrap());
+                let length = u64::from_le_bytes(entry[12..20].try_into().unwrap());
+                let end = start.checked_add(length).filter(|&end|end<=file_length as u64).ok_or_else(||WordFileError::Truncated{what:format!("section {}",tag_name(&tag)),needed:start.saturating_add(length) as usize,file_length})?;
+                if sections.iter().any(|(t,_,_)|*t==tag) { return Err(WordFileError::DuplicateSection(tag)); }
+                sections.push((tag,start as usize,end as usize));
+            }
+            // Check the checksum after the section table so that a truncated file is reported as such.
+            let stored = read_u32(12);
+            let computed = crc32fast::hash(&data[HEADER_LENGTH..]);
+            if stored!=computed { return Err(WordFileError::ChecksumMismatch {stored,computed}); }
+            let find = |tag:SectionTag| sections.iter().find(|(t,_,_)|*t==tag).map(|&(_,start,end)|(start,end)).ok_or(WordFileError::MissingSection(tag));
+            let check_length = |section:SectionTag,(start,end):(usize,usize),expected:Option<usize>| {
+                if Some(end-start)==expected { Ok(start) } else { Err(WordFileError::SectionWrongLength{section,expected:expected.unwrap_or(usize::MAX),found:end-start}) }
+            };
+            let (num_synonyms,synonym_table_start,synonyms_start) = if let Ok((start,end)) = find(VARIABLE_SYNONYMS_SECTION) {
+                let table_length = number_words.checked_add(1).and_then(|n|n.checked_mul(4));
+                let entries_start = table_length.and_then(|n|n.checked_add(start)).filter(|&entries|entries<=end).ok_or(WordFileError::SectionWrongLength{section:VARIABLE_SYNONYMS_SECTION,expected:table_length.unwrap_or(usize::MAX),found:end-start})?;
+                let num_entries = read_u32(entries_start-4) as usize;
+                check_length(VARIABLE_SYNONYMS_SECTION,(start,end),num_entries.checked_mul(8).and_then(|n|n.checked_add(entries_start-start)))?;
+                (0,Some(start),entries_start)
+            } else {
+                let synonyms = find(SYNONYMS_SECTION)?;
+                let num_synonyms = read_u32_checked(&data[..synonyms.1],synonyms.0).ok_or(WordFileError::SectionWrongLength{section:SYNONYMS_SECTION,expected:4,found:synonyms.1-synonyms.0})? as usize;
+                (num_synonyms,None,check_length(SYNONYMS_SECTION,synonyms,number_words.checked_mul(num_synonyms).and_then(|n|n.checked_mul(8)).and_then(|n|n.checked_add(4)))?+4)
+            };
+            let alphabetic_order_start = check_length(ALPHABETICAL_SECTION,find(ALPHABETICAL_SECTION)?,number_words.checked_mul(4))?;
+            let offsets_start = check_length(OFFSETS_SECTION,find(OFFSETS_SECTION)?,number_words.checked_mul(4))?;
+            let (strings_start,strings_end) = find(STRINGS_SECTION)?;
+            let frequency_start = match find(FREQUENCY_SECTION) {
+                Ok(section) => Some(check_length(FREQUENCY_SECTION,section,number_words.checked_add(1).and_then(|n|n.checked_mul(8)))?),
+                Err(_) => None,
+            };
+            let vectors = match find(VECTORS_SECTION) {
+                Ok((start,end)) => {
+                    let header = |offset:usize| read_u32_checked(&data[..end],start+offset).ok_or(WordFileError::SectionWrongLength{section:VECTORS_SECTION,expected:8,found:end-start});
+                    let dimensions = header(0)? as usize;
+                    let code = header(4)?;
+                    let quantization = VectorQuantization::from_code(code).ok_or(WordFileError::UnknownVectorQuantization(code))?;
+                    let bytes_per_vector = quantization.bytes_per_vector(dimensions).unwrap_or(usize::MAX);
+                    check_length(VECTORS_SECTION,(start,end),bytes_per_vector.checked_mul(number_words).and_then(|n|n.checked_add(8)))?;
+                    Some(VectorsLocation{ start:start+8, dimensions, quantization, bytes_per_vector })
+                }
+                Err(_) => None,
+            };
+            let lemmas_start = match find(LEMMAS_SECTION) {
+                Ok(section) => Some(check_length(LEMMAS_SECTION,section,number_words.checked_mul(4))?),
+                Err(_) => None,
+            };
+            let section_lengths = sections.iter().map(|&(tag,start,end)|(tag,end-start)).collect();
+            let prefix_section = find(PREFIX_SECTION).ok();
+            let normalization = match find(NORMALIZATION_SECTION) {
+                Ok(section) => Normalization::from_flags(read_u32(check_length(NORMALIZATION_SECTION,section,Some(4))?)),
+                Err(_) => Normalization::default(),
+            };
+            WordsInFile{ data, format_version, number_words, num_synonyms, synonym_table_start, synonyms_start, alphabetic_order_start, offsets_start, strings_start, strings_end, frequency_start, vectors, lemmas_start, loaded_lemmas:None, section_lengths, prefix_section, built_prefix_index:vec![], normalization, synonym_overlay:None }
+        } else {
+            let number_words = read_u32(4) as usize;
+            let num_synonyms = read_u32(8) as usize;
+            let synonyms_start = 12;
+            let alphabetic_order_start = end_of("the synonyms",synonyms_start,number_words,num_synonyms.saturating_mul(8),file_length)?;
+            let offsets_start = end_of("the alphabetical list",alphabetic_order_start,number_words,4,file_length)?;
+            let strings_length_start = end_of("the offsets",offsets_start,number_words,4,file_length)?;
+            let strings_start = end_of("the strings length",strings_length_start,1,4,file_length)?;
+            let strings_end = end_of("the strings",strings_start,1,read_u32(strings_length_start) as usize,file_length)?;
+            WordsInFile{ data, format_version:0, number_words, num_synonyms, synonym_table_start:None, synonyms_start, alphabetic_order_start, offsets_start, strings_start, strings_end, frequency_start:None, vectors:None, lemmas_start:None, loaded_lemmas:None, section_lengths:vec![], prefix_section:None, built_prefix_index:vec![], normalization:Normalization::default(), synonym_overlay:None }
+        };
+        res.validate()?;
+        if res.prefix_section.is_none() {
+            let alphabetical_order : Vec<WordIndex> = (0..res.number_words).map(|position|WordIndex(res.read_u32(res.alphabetic_order_start+4*position))).collect();
+            res.built_prefix_index = prefix_section(&res,&alphabetical_order).map_err(|e|WordFileError::BadPrefixIndex(e.to_string()))?;
+        }
+        Ok(res)
+    }
+
+    /// Check that all the sections are self consistent, so that later accesses will not panic.
+    fn validate(&self) -> Result<(),WordFileError> {
+        let number_words = self.number_words as u32;
+        for word in 0..self.number_words {
+            let offset = self.read_u32(self.offsets_start+4*word);
+            let start = self.strings_start.checked_add(offset as usize).filter(|&start|start<self.strings_end).ok_or(WordFileError::StringOffsetOutOfRange{word:WordIndex(word as u32),offset})?;
+            let buf = &self.data[start..self.strings_end];
+            let len = buf.iter().position(|b|*b==0).ok_or(WordFileError::StringNotTerminated(WordIndex(word as u32)))?;
+            std::str::from_utf8(&buf[..len]).map_err(|_|WordFileError::StringNotUtf8(WordIndex(word as u32)))?;
+            if let Some(table_start) = self.synonym_table_start {
+                let (first,last) = (self.read_u32(table_start+4*word),self.read_u32(table_start+4*word+4));
+                if first>last { return Err(WordFileError::SynonymTableNotIncreasing(WordIndex(word as u32))); }
+            }
+            let (start,count) = self.synonym_range(WordIndex(word as u32));
+            for position in 0..count {
+                let offset = start+8*position;
+                let value = self.read_u32(offset);
+                if value>=number_words { return Err(WordFileError::WordIndexOutOfRange{section:if self.synonym_table_start.is_some() {VARIABLE_SYNONYMS_SECTION} else {SYNONYMS_SECTION},position:(offset-self.synonyms_start)/8,value}); }
+                if !self.read_f32(offset+4).is_finite() { return Err(WordFileError::BadScore{word:WordIndex(word as u32),position}); }
+            }
+        }
+        let mut previous : Option<&str> = None;
+        let mut seen = vec![false;self.number_words];
+        for position in 0..self.number_words {
+            let value = self.read_u32(self.alphabetic_order_start+4*position);
+            if value>=number_words { return Err(WordFileError::WordIndexOutOfRange{section:ALPHABETICAL_SECTION,position,value}); }
+            if seen[value as usize] { return Err(WordFileError::NotAlphabetical{position}); }
+            seen[value as usize]=true;
+            let word = self.word(WordIndex(value));
+            if let Some(previous) = previous {
+                if previous>word { return Err(WordFileError::NotAlphabetical{position}); }
+            }
+            previous=Some(word);
+        }
+        if let Some((start,end)) = self.prefix_section {
+            let map = fst::Map::new(&self.data[start..end]).map_err(|e|WordFileError::BadPrefixIndex(e.to_string()))?;
+            let mut stream = map.stream();
+            let mut position = 0;
+            while let Some((word,value)) = fst::Streamer::next(&mut stream) {
+                if value>=number_words as u64 { return Err(WordFileError::WordIndexOutOfRange{section:PREFIX_SECTION,position,value:value.min(u32::MAX as u64) as u32}); }
+                if word!=self.word(WordIndex(value as u32)).as_bytes() { return Err(WordFileError::BadPrefixIndex(format!("entry {} is not word {}",position,value))); }
+                position+=1;
+            }
+        }
+        if let Some(start) = self.lemmas_start {
+            for position in 0..self.number_words {
+                let value = self.read_u32(start+4*position);
+                if value>=number_words { return Err(WordFileError::WordIndexOutOfRange{section:LEMMAS_SECTION,position,value}); }
+            }
+        }
+        Ok(())
+    }
+
+    /// The version of the file format, 0 meaning the original unversioned format. That has no checksum, so callers
+    /// may want to suggest recreating the file.
+    pub fn format_version(&self) -> u32 { self.format_version }
+
+    /// The tag and length in bytes of each section, in the order they are in the file. Empty for unversioned files, which have no sections.
+    pub fn sections(&self) -> &[(SectionTag,usize)] { &self.section_lengths }
+
+    /// How text should be normalized before looking it up in this vocabulary.
+    pub fn normalization(&self) -> Normalization { self.normalization }
+
+    /// The length of the whole file in bytes.
+    pub fn file_length(&self) -> usize { self.data.len() }
+
+    fn four_array(&self,offset:usize) -> [u8;4] { [self.data[offset],self.data[offset+1],self.data[offset+2],self.data[offset+3]] }
     fn read_u32(&self,offset:usize) -> u32 { u32::from_le_bytes(self.four_array(offset)) }
     fn read_f32(&self,offset:usize) -> f32 { f32::from_le_bytes(self.four_array(offset)) }
+    fn read_u64(&self,offset:usize) -> u64 { u64::from_le_bytes(self.data[offset..offset+8].try_into().unwrap()) }
+
+    /// The number of times the word occurs in the corpus the word counts came from, or None if the file has no word counts.
+    pub fn frequency(&self,word:WordIndex) -> Option<u64> {
+        self.frequency_start.map(|start|self.read_u64(start+8+8*word.0 as usize))
+    }
+
+    /// The sum of all the word counts, or None if the file has no word counts.
+    pub fn total_frequency(&self) -> Option<u64> {
+        self.frequency_start.map(|start|self.read_u64(start))
+    }
+
+    /// The inverse document frequency, ln(total/count), or None if the file has no word counts.
+    /// The counts are generally word occurrences rather than documents containing the word, which is close enough for words that are not very common.
+    /// A word with count 0 is treated as if it had count 1.
+    pub fn idf(&self,word:WordIndex) -> Option<f64> {
+        let total = self.total_frequency()?;
+        let count = self.frequency(word)?;
+        Some((total.max(1) as f64/count.max(1) as f64).ln())
+    }
+
+    /// Whether there is a lemma table, either in the file or loaded separately.
+    pub fn has_lemmas(&self) -> bool { self.loaded_lemmas.is_some() || self.lemmas_start.is_some() }
+
+    /// The lemma of a word, e.g. "vaccinate" for "vaccinated". A word is its own lemma if it has no other, or there is no lemma table.
+    pub fn lemma(&self,word:WordIndex) -> WordIndex {
+        match (&self.loaded_lemmas,self.lemmas_start) {
+            (Some(lemmas),_) => lemmas[word.0 as usize],
+            (None,Some(start)) => WordIndex(self.read_u32(start+4*word.0 as usize)),
+            (None,None) => word,
+        }
+    }
+
+    /// Use the given lemmas from now on, instead of any in the file.
+    /// Returns the number of (word,lemma) pairs ignored as one of the words is not in this vocabulary.
+    pub fn apply_lemmas(&mut self,lemmas:&Lemmas) -> usize {
+        let (table,ignored) = lemmas.resolve(self,self.normalization);
+        self.loaded_lemmas=Some(table);
+        ignored
+    }
+
+    /// How the vectors are stored, or None if the file has no vectors.
+    pub fn vector_quantization(&self) -> Option<VectorQuantization> { self.vectors.as_ref().map(|v|v.quantization) }
+
+    /// The number of dimensions of the vectors, or None if the file has no vectors.
+    pub fn vector_dimensions(&self) -> Option<usize> { self.vectors.as_ref().map(|v|v.dimensions) }
+
+    /// The (quantized) vector for a word, or None if the file has no vectors.
+    pub fn vector(&self,word:WordIndex) -> Option<WordVec> {
+        let location = self.vectors.as_ref()?;
+        let start = location.start+word.0 as usize*location.bytes_per_vector;
+        let values = match location.quantization {
+            VectorQuantization::F16 => self.data[start..start+location.bytes_per_vector].chunks_exact(2).map(|b|half::f16::from_le_bytes([b[0],b[1]]).to_f64()).collect(),
+            VectorQuantization::Int8 => {
+                let scale = self.read_f32(start) as f64;
+                self.data[start+4..start+location.bytes_per_vector].iter().map(|b|*b as i8 as f64*scale).collect()
+            }
+        };
+        Some(WordVec::new(values))
+    }
+
+    /// The cosine between the vectors of two words, or None if the file has no vectors.
+    /// Unlike [Self::synonyms], this works for any two words, not just the closest.
+    pub fn cosine(&self,word1:WordIndex,word2:WordIndex) -> Option<f64> {
+        Some(self.vector(word1)?.cosine(&self.vector(word2)?))
+    }
+
+    /// The mean of the vectors of the given words, a simple embedding of a sentence or phrase.
+    /// None if the file has no vectors or there are no words.
+    pub fn mean_vector(&self,words:&[WordIndex]) -> Option<WordVec> {
+        let dimensions = self.vector_dimensions()?;
+        if words.is_empty() { return None; }
+        let mut sum = vec![0.0;dimensions];
+        for word in words {
+            for (total,v) in sum.iter_mut().zip(self.vector(*word)?.values()) { *total+=v; }
+        }
+        Some(WordVec::new(sum.into_iter().map(|v|v/words.len() as f64).collect()))
+    }
+
+    /// The position in the file of the first synonym of the word, and the number of synonyms.
+    fn synonym_range(&self,word:WordIndex) -> (usize,usize) {
+        match self.synonym_table_start {
+            Some(table_start) => {
+                let first = self.read_u32(table_start+4*word.0 as usize) as usize;
+                let last = self.read_u32(table_start+4*word.0 as usize+4) as usize;
+                (self.synonyms_start+8*first,last-first)
+            }
+            None => (self.synonyms_start+word.0 as usize*(8*self.num_synonyms),self.num_synonyms),
+        }
+    }
 
+    /// The synonyms of a word, best first. Each has a score (cosine) between -1 and 1, generally positive.
     pub fn synonyms(&self,word:WordIndex) -> Vec<WordAndValue> {
-        let mut offset = self.synonyms_start+word.0 as usize*(8*self.num_synonyms);
-        let mut res = vec![];
-        for _ in 0..self.num_synonyms {
+        let (mut offset,count) = self.synonym_range(word);
+        let mut res = Vec::with_capacity(count);
+        for _ in 0..count {
             let word = WordIndex(self.read_u32(offset));
             offset+=4;
             let value = self.read_f32(offset);
             offset+=4;
             res.push(WordAndValue{ word, value });
         }
-        res
+        match &self.synonym_overlay {
+            Some(overlay) => overlay.apply(word,res),
+            None => res,
+        }
     }
 
-    /// find a word that is a prefix of the given string.
-    /// If there are multiple ones, find the longest.
-    /// Now it is not clear how to handle punctuation here. You want to get "u.s.a." to contain punctuation, but not "where?" This is a problem if there is a word like "wherefore" in the dictionary, which the binary search could end up saying comes before "where?" so "where" will never be seen.
-    pub fn index_starting(&self,word:&str) -> Option<(WordIndex,usize)> {
-        let low  = 0; // values less than this are NOT the word.
-        let high = self.number_words; // values equal to or higher than this are NOT the word.
-        self.index_starting_between(word,low,high)
+    /// Apply hand made corrections to the synonyms from now on, replacing any previously applied.
+    /// Returns the words in the overlay that are not in this vocabulary, and so are ignored.
+    pub fn apply_synonym_overlay(&mut self,overlay:&SynonymOverlay) -> Vec<String> {
+        let resolved = overlay.resolve(self,self.normalization);
+        let unknown = resolved.unknown_words.clone();
+        self.synonym_overlay = if resolved.is_empty() { None } else { Some(resolved) };
+        unknown
     }
 
-    /// like index_starting but between low(inclusive) and high(exclusive).
-    fn index_starting_between(&self,word:&str,mut low:usize,mut high:usize) -> Option<(WordIndex,usize)> {
-        let mut prefix : Option<(WordIndex,usize)> = None; // This is a valid prefix. There may be better ones while still in the loop.
-        while low<high {
-            let mid = (low+high)/2;
-            let word_index = WordIndex(self.read_u32(self.alphabetic_order_start+4*mid));
-            let mid_word = self.word(word_index);
-            //println!("comparing word={}, mid_word={}",word,mid_word);
-            match word.char_indices().zip(mid_word.char_indices()).find(|((_,word_char),(_,mid_char))|word_char!=mid_char)  { // find the first differing character
-                None => { // the shorter is a subset of the longer.
-                    if word.len()>=mid_word.len() { // mid_word is a prefix of word!
-                        let word_used = mid_word.len(); // TODO is this unicode safe? Is mid_word.len the same as the prefix? Could be different if there are different length encodings of the same char.
-                        if prefix.is_none() || prefix.unwrap().1 < word_used { prefix=Some((word_index,word_used)) }
-                        low=mid+1; // try to look for a longer prefix.
-                    } else { // mid_word is longer than word.
-                        high=mid;
-                    }
-                }
-                Some(((word_pos,word_char),(_mid_pos,mid_char))) => {
-                    if word_char<mid_char {
-                        high=mid;
-                    } else {
-                        if word_pos>0 && !word_char.is_alphanumeric() {
-                            // This is the possible case where word="where?" and mid_word="wherefore". Check for this special case.
-                            if let Some(special_case) = self.index_starting_between(&word[..word_pos],low,mid-1) {
-                                if prefix.is_none() || prefix.unwrap().1 < special_case.1 { prefix=Some(special_case) }
-                            }
-                        }
-                        low=mid+1;
-                    }
-                }
+    /// The "PRFX" section, or the equivalent built when loading.
+    fn prefix_index(&self) -> fst::raw::Fst<&[u8]> {
+        let bytes = match self.prefix_section {
+            Some((start,end)) => &self.data[start..end],
+            None => &self.built_prefix_index,
+        };
+        fst::raw::Fst::new(bytes).expect("Prefix index checked when loaded")
+    }
+
+    /// Find the longest word that is a prefix of the given string, and its length in bytes.
+    ///
+    /// This is a plain prefix match, so for "where?" it finds "where" even if "wherefore" is in the vocabulary,
+    /// and for "u.s.a. is" it finds "u.s.a." if that is in the vocabulary. The length is always at a character boundary.
+    ///
+    /// The exception is phrases (words containing a space), which are only found if they end at the end of a word,
+    /// so "climate changes" finds "climate" rather than "climate change" followed by "s".
+    pub fn index_starting(&self,word:&str) -> Option<(WordIndex,usize)> {
+        let fst = self.prefix_index();
+        let mut node = fst.root();
+        let mut output = fst::raw::Output::zero();
+        let mut longest = None;
+        for (position,&byte) in word.as_bytes().iter().enumerate() {
+            let transition = match node.find_input(byte) { Some(i) => node.transition(i), None => break };
+            output = output.cat(transition.out);
+            node = fst.node(transition.addr);
+            if node.is_final() {
+                let end = position+1;
+                let is_phrase = word.as_bytes()[..end].contains(&b' ');
+                if !is_phrase || !word[end..].starts_with(|c:char|c.is_alphanumeric()) { longest = Some((WordIndex(output.cat(node.final_output()).value() as u32),end)); }
             }
         }
-        prefix
+        longest
     }
 }
 
@@ -164,8 +831,8 @@ impl WordSource for WordsInFile {
     fn len(&self) -> usize { self.number_words }
     fn word(&self,index:WordIndex) -> &str {
         let start = self.strings_start+self.read_u32(self.offsets_start+4*index.0 as usize) as usize;
-        let buf = &self.mmap[start..];
-        let len = buf.iter().position(|b|*b==0).expect("String not null terminated");
+        let buf = &self.data[start..self.strings_end];
+        let len = buf.iter().position(|b|*b==0).expect("String not null terminated"); // checked in validate()
         let buf = &buf[..len];
         std::str::from_utf8(buf).expect("String not utf-8")
     }
@@ -188,3 +855,184 @@ impl WordSource for WordsInFile {
 
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{small_glove, small_word_file, temp_path};
+
+    #[test]
+    fn round_trip() {
+        let words = WordsInFile::read_word_file(small_word_file("round_trip")).unwrap();
+        assert_eq!(words.format_version(),WORD_FILE_FORMAT_VERSION);
+        assert_eq!(words.len(),9);
+        assert_eq!(words.index("cat"),Some(WordIndex(3)));
+        assert_eq!(words.word(WordIndex(6)),"wherefore");
+        let synonyms = words.synonyms(WordIndex(3));
+        assert_eq!(synonyms.len(),3);
+        assert!(synonyms.iter().any(|s|s.word==WordIndex(4)));
+    }
+
+    #[test]
+    fn word_counts() {
+        let (words,wordvecs) = small_glove("counts");
+        let counts_path = temp_path("counts.vocab");
+        std::fs::write(&counts_path,"the 1000\ncat 6\nCat 4\nnotaword 5\n").unwrap();
+        let counts = crate::word::read_word_counts(&counts_path,&words,Normalization::default()).unwrap();
+        let path = temp_path("counts.bin");
+        write_word_file_with_options(&path,&words,&wordvecs,&WordFileOptions{num_synonyms:3,word_counts:Some(counts),..Default::default()}).unwrap();
+        let words = WordsInFile::read_word_file(&path).unwrap();
+        assert_eq!(words.frequency(WordIndex(3)),Some(10));
+        assert_eq!(words.frequency(WordIndex(4)),Some(0));
+        assert_eq!(words.total_frequency(),Some(1010));
+        assert!(words.idf(WordIndex(0)).unwrap()<words.idf(WordIndex(3)).unwrap());
+        assert_eq!(WordsInFile::read_word_file(small_word_file("no_counts")).unwrap().idf(WordIndex(0)),None);
+    }
+
+    #[test]
+    fn synonym_cutoff() {
+        let (words,wordvecs) = small_glove("cutoff");
+        let path = temp_path("cutoff.bin");
+        write_word_file_with_options(&path,&words,&wordvecs,&WordFileOptions{num_synonyms:4,min_cosine:Some(0.9),..Default::default()}).unwrap();
+        let words = WordsInFile::read_word_file(&path).unwrap();
+        let mut cat : Vec<WordIndex> = words.synonyms(WordIndex(3)).iter().map(|s|s.word).collect();
+        cat.sort();
+        assert_eq!(cat,vec![WordIndex(4),WordIndex(5)]); // dog and kitten
+        assert_eq!(words.synonyms(WordIndex(1)).len(),1); // "?" is only similar to "the"
+    }
+
+    #[test]
+    fn stored_vectors() {
+        let (words,wordvecs) = small_glove("vectors");
+        for quantization in [VectorQuantization::F16,VectorQuantization::Int8] {
+            let path = temp_path("vectors.bin");
+            write_word_file_with_options(&path,&words,&wordvecs,&WordFileOptions{num_synonyms:3,vectors:Some(quantization),..Default::default()}).unwrap();
+            let file = WordsInFile::read_word_file(&path).unwrap();
+            assert_eq!(file.vector_quantization(),Some(quantization));
+            assert_eq!(file.vector_dimensions(),Some(3));
+            for i in words.all_indices() {
+                for j in words.all_indices() {
+                    assert!((file.cosine(i,j).unwrap()-wordvecs.get(i).cosine(wordvecs.get(j))).abs()<0.01);
+                }
+            }
+            let mean = file.mean_vector(&[WordIndex(2),WordIndex(3)]).unwrap(); // where and cat
+            assert!((mean.values()[1]-0.5).abs()<0.01);
+            let rewritten = temp_path("vectors_rewritten.bin");
+            WordFileContents::from_word_file(&file).write(&rewritten).unwrap();
+            let rewritten = WordsInFile::read_word_file(&rewritten).unwrap();
+            assert_eq!(rewritten.vector(WordIndex(7)).unwrap().values(),file.vector(WordIndex(7)).unwrap().values());
+        }
+        assert!(WordsInFile::read_word_file(small_word_file("no_vectors")).unwrap().vector(WordIndex(0)).is_none());
+    }
+
+    #[test]
+    fn lemmas() {
+        let (words,wordvecs) = small_glove("lemmas");
+        let lemmas = Lemmas{ pairs: vec![("vaccination".to_string(),"vaccine".to_string()),("kittens".to_string(),"kitten".to_string())] };
+        let path = temp_path("lemmas.bin");
+        let report = write_word_file_with_options(&path,&words,&wordvecs,&WordFileOptions{num_synonyms:3,lemmas:Some(lemmas),..Default::default()}).unwrap();
+        assert_eq!(report.ignored_lemmas,1);
+        let mut file = WordsInFile::read_word_file(&path).unwrap();
+        assert!(file.has_lemmas());
+        assert_eq!(file.lemma(WordIndex(8)),WordIndex(7));
+        assert_eq!(file.lemma(WordIndex(3)),WordIndex(3));
+        assert_eq!(WordFileContents::from_word_file(&file).lemmas.unwrap()[8],WordIndex(7));
+        file.apply_lemmas(&Lemmas{ pairs: vec![("Dog".to_string(),"ＣＡＴ".to_string())] }); // normalized like the vocabulary.
+        assert_eq!(file.lemma(WordIndex(8)),WordIndex(8));
+        assert_eq!(file.lemma(WordIndex(4)),WordIndex(3));
+    }
+
+    #[test]
+    fn inconsistent_contents_rejected() {
+        let file = WordsInFile::read_word_file(small_word_file("inconsistent")).unwrap();
+        let path = temp_path("inconsistent_rewritten.bin");
+        let is_invalid_input = |contents:&WordFileContents| contents.write(&path).unwrap_err().kind()==std::io::ErrorKind::InvalidInput;
+        let mut contents = WordFileContents::from_word_file(&file);
+        contents.word_counts = Some(vec![1;3]);
+        assert!(is_invalid_input(&contents));
+        let mut contents = WordFileContents::from_word_file(&file);
+        contents.lemmas = Some(vec![WordIndex(0)]);
+        assert!(is_invalid_input(&contents));
+        let mut contents = WordFileContents::from_word_file(&file);
+        let mut vectors = WordVecs::default();
+        for i in 0..file.len() { vectors.push(WordVec::new(vec![1.0;if i==4 { 2 } else { 3 }])); }
+        contents.vectors = Some((VectorQuantization::F16,vectors));
+        assert!(is_invalid_input(&contents));
+        contents.vectors = None;
+        contents.synonyms.pop();
+        assert!(is_invalid_input(&contents));
+    }
+
+    /// The longest word in the list that is a prefix of text, by checking every word.
+    fn brute_force_prefix(words:&[String],text:&str) -> Option<(WordIndex,usize)> {
+        words.iter().enumerate().filter(|(_,w)|text.starts_with(w.as_str())).max_by_key(|(_,w)|w.len()).map(|(i,w)|(WordIndex(i as u32),w.len()))
+    }
+
+    #[test]
+    fn prefix_punctuation() {
+        let words = WordsInFile::read_word_file(small_word_file("prefix")).unwrap();
+        assert_eq!(words.index_starting("where? now"),Some((WordIndex(2),5)));
+        assert_eq!(words.index_starting("wherefore art"),Some((WordIndex(6),9)));
+        assert_eq!(words.index_starting("?"),Some((WordIndex(1),1)));
+        assert_eq!(words.index_starting("xyz"),None);
+    }
+
+    #[test]
+    fn phrases_match_longest() {
+        let mut words = MemoryWords::default();
+        for w in ["climate","change","changes","climate change"] { words.add(w); }
+        let path = temp_path("phrases.bin");
+        write_contents(&path,&words,&[vec![],vec![],vec![],vec![]],None,None,None,Normalization::default()).unwrap();
+        let words = WordsInFile::read_word_file(&path).unwrap();
+        assert_eq!(words.index_starting("climate change is"),Some((WordIndex(3),14)));
+        assert_eq!(words.index_starting("climate change."),Some((WordIndex(3),14)));
+        assert_eq!(words.index_starting("climate changes"),Some((WordIndex(0),7)));
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prefix_matches_brute_force(word_list in proptest::collection::hash_set("[ab.?é']{1,5}",1..40),texts in proptest::collection::vec("[ab.?é' ]{0,8}",10)) {
+            let word_list : Vec<String> = word_list.into_iter().collect();
+            let mut words = MemoryWords::default();
+            for w in &word_list { words.add(w); }
+            let path = temp_path("prefix_proptest.bin");
+            write_contents(&path,&words,&vec![vec![];word_list.len()],None,None,None,Normalization::default()).unwrap();
+            let in_file = WordsInFile::read_word_file_into_memory(&path).unwrap();
+            for text in &texts {
+                proptest::prop_assert_eq!(in_file.index_starting(text),brute_force_prefix(&word_list,text));
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_files_give_errors() {
+        let path = small_word_file("corrupt");
+        let good = std::fs::read(&path).unwrap();
+        let check = |contents:&[u8]| { std::fs::write(&path,contents).unwrap(); WordsInFile::read_word_file(&path).err().unwrap() };
+        assert!(matches!(check(b"WRONG MAGIC NUMBER"),WordFileError::BadMagic));
+        assert!(matches!(check(&good[..good.len()-5]),WordFileError::Truncated{..}));
+        let mut flipped = good.clone();
+        *flipped.last_mut().unwrap()^=1;
+        assert!(matches!(check(&flipped),WordFileError::ChecksumMismatch{..}));
+        let mut future = good.clone();
+        future[8..12].copy_from_slice(&(WORD_FILE_FORMAT_VERSION+1).to_le_bytes());
+        assert!(matches!(check(&future),WordFileError::UnsupportedVersion(_)));
+    }
+
+    #[test]
+    fn legacy_unversioned_file() {
+        let path = temp_path("legacy.bin");
+        let mut contents = b"WORD".to_vec();
+        for v in [2u32,1,1,0.5f32.to_bits(),0,0.5f32.to_bits(),0,1,0,2,4] { contents.extend_from_slice(&v.to_le_bytes()); }
+        contents.extend_from_slice(b"a\0b\0");
+        std::fs::write(&path,&contents).unwrap();
+        let words = WordsInFile::read_word_file(&path).unwrap();
+        assert_eq!(words.format_version(),0);
+        assert_eq!(words.index("b"),Some(WordIndex(1)));
+        assert_eq!(words.index_starting("bc"),Some((WordIndex(1),1))); // prefix index built on loading.
+        assert_eq!(words.synonyms(WordIndex(0))[0].word,WordIndex(1));
+        contents[12]=7; // synonym of word 0 is now word 7, which does not exist.
+        std::fs::write(&path,&contents).unwrap();
+        assert!(matches!(WordsInFile::read_word_file(&path),Err(WordFileError::WordIndexOutOfRange{..})));
+    }
+}
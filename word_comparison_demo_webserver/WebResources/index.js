@@ -23,6 +23,25 @@ function updateAllList() {
     getWebJSON("get_all_questions",success,failure);
 }
 
+/** Add text to an element, with the given ranges of bytes (in UTF-8) in spans of class "matched".
+ * @param where{HTMLElement} element to add to
+ * @param text{string} text to add
+ * @param ranges{{start:number,end:number}[]} byte ranges to highlight, possibly overlapping */
+function addHighlighted(where,text,ranges) {
+    const bytes = new TextEncoder().encode(text);
+    const decoder = new TextDecoder();
+    const highlighted = new Array(bytes.length+1).fill(false);
+    for (const range of ranges) for (let i=range.start;i<range.end;i++) highlighted[i]=true;
+    let start = 0;
+    for (let i=1;i<=bytes.length;i++) {
+        if (i===bytes.length || highlighted[i]!==highlighted[start]) {
+            const piece = decoder.decode(bytes.slice(start,i));
+            if (highlighted[start]) add(where,"span","matched").innerText=piece; else addText(where,piece);
+            start=i;
+        }
+    }
+}
+
 let currently_pending_check_similarity = false;
 let should_do_new_check_similarity = false;
 function checkSimilarity() {
@@ -43,10 +62,8 @@ function checkSimilarity() {
             for (const possibility of data.Ok) {
                 let line = add(div,"div","SimilarQuestionLine");
                 add(line,"span","score").innerText = possibility.score.toFixed(2);
-                function foundQuestion(data) {
-                    if (data.Ok) addText(line," "+data.Ok);
-                }
-                getWebJSON(getURL("get_question",{id:possibility.id}),foundQuestion,failure);
+                addText(line," ");
+                addHighlighted(line,possibility.question,possibility.matches.map(m=>m.question));
             }
             pendingCheck();
         }
@@ -56,7 +73,7 @@ function checkSimilarity() {
         pendingCheck();
     }
     currently_pending_check_similarity=true;
-    getWebJSON(getURL("get_similar",{question:document.getElementById("entry").value}),success,failurePending);
+    getWebJSON(getURL("get_similar_with_matches",{question:document.getElementById("entry").value}),success,failurePending);
 }
 
 function addEntry() {
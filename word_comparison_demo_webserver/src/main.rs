@@ -9,9 +9,18 @@ use actix_web::web::Json;
 use actix_web::{get, post};
 use async_std::sync::Mutex;
 use word_comparison::word_file::{WordsInFile, WORD_MMAP_FILE};
+use word_comparison::reloadable_word_file::ReloadableWordsInFile;
+use word_comparison::synonym_overlay::SynonymOverlay;
+use word_comparison::lemmas::Lemmas;
+use word_comparison::stopwords::StopwordPolicy;
+use word_comparison::spelling::SpellingCorrector;
 use word_comparison::listed_keywords::ListedKeywords;
-use word_comparison::comparison_list::{add_question, find_similar_in_database, ScoredIDs};
+use word_comparison::tokenizer::{SpellingCorrection, Tokenizer};
+use word_comparison::multilingual::{LanguageVocabularies, MultilingualTokenizer};
+use word_comparison::comparison_list::{add_question, find_similar_in_database, find_similar_in_database_with_matches, explain_query, QueryExplanation, ScoredIDs, ScoredIDsWithMatches};
 use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use word_comparison::database_backend::{InternalQuestionId, WordComparisonDatabaseBackend};
 use word_comparison::flatfile_database_backend::FlatfileDatabaseBackend;
 
@@ -44,11 +53,29 @@ struct QuerySimilarity {
 
 /// Get some particular question
 #[get("/get_similar")]
-async fn get_similar(query:web::Query<QuerySimilarity>, question_db: web::Data<Mutex<QuestionDatabase>>, words: web::Data<WordsInFile>,keywords: web::Data<ListedKeywords>) -> Json<Result<Vec<ScoredIDs<QuestionID>>,String>> {
-    let similar = find_similar_in_database(question_db.lock().await.deref_mut(),&query.question,&words,&keywords);
+async fn get_similar(query:web::Query<QuerySimilarity>, question_db: web::Data<Mutex<QuestionDatabase>>, vocabulary: web::Data<CurrentVocabulary>, other_languages: web::Data<LanguageVocabularies>) -> Json<Result<Vec<ScoredIDs<QuestionID>>,String>> {
+    let mut question_db = question_db.lock().await;
+    let vocabulary = vocabulary.get();
+    let similar = find_similar_in_database(question_db.deref_mut(),&query.question,&vocabulary.query_tokenizer(&other_languages),&vocabulary.stopwords);
     Json(similar.map_err(|e|e.to_string()))
 }
 
+/// Get similar questions, with their text and which parts of them matched the query, for highlighting.
+#[get("/get_similar_with_matches")]
+async fn get_similar_with_matches(query:web::Query<QuerySimilarity>, question_db: web::Data<Mutex<QuestionDatabase>>, vocabulary: web::Data<CurrentVocabulary>, other_languages: web::Data<LanguageVocabularies>) -> Json<Result<Vec<ScoredIDsWithMatches<QuestionID>>,String>> {
+    let mut question_db = question_db.lock().await;
+    let vocabulary = vocabulary.get();
+    let similar = find_similar_in_database_with_matches(question_db.deref_mut(),&query.question,&vocabulary.query_tokenizer(&other_languages),&vocabulary.stopwords);
+    Json(similar.map_err(|e|e.to_string()))
+}
+
+/// Explain how a query is tokenized and what it is searched for, for debugging.
+#[get("/explain")]
+async fn explain(query:web::Query<QuerySimilarity>, vocabulary: web::Data<CurrentVocabulary>, other_languages: web::Data<LanguageVocabularies>) -> Json<QueryExplanation> {
+    let vocabulary = vocabulary.get();
+    Json(explain_query(&query.question,&vocabulary.query_tokenizer(&other_languages),&vocabulary.stopwords))
+}
+
 /// find the path containing web resources, static web files that will be served.
 /// This is usually in the directory `WebResources` but the program may be run from
 /// other directories. To be as robust as possible it will try likely possibilities.
@@ -70,34 +97,82 @@ struct Publish {
 }
 
 #[post("/submit_question")]
-async fn submit_question(command : web::Json<Publish>, question_db: web::Data<Mutex<QuestionDatabase>>, words: web::Data<WordsInFile>,keywords: web::Data<ListedKeywords>) -> Json<Result<InternalQuestionId,String>> {
-    let external_id = question_db.lock().await.len()*2+7;
-    let res = add_question(question_db.lock().await.deref_mut(),&command.data,external_id as u32,&words,&keywords);
+async fn submit_question(command : web::Json<Publish>, question_db: web::Data<Mutex<QuestionDatabase>>, vocabulary: web::Data<CurrentVocabulary>, other_languages: web::Data<LanguageVocabularies>) -> Json<Result<InternalQuestionId,String>> {
+    let mut question_db = question_db.lock().await;
+    let external_id = question_db.len()*2+7;
+    let vocabulary = vocabulary.get();
+    let res = add_question(question_db.deref_mut(),&command.data,external_id as u32,&vocabulary.tokenizer(&other_languages),&vocabulary.stopwords);
     Json(res.map_err(|e|e.to_string()))
 }
 
+/// Everything that depends on the general vocabulary. This is replaced as a whole when the vocabulary is reloaded,
+/// so the spelling corrector, listed keywords and stopwords always match the words.
+struct Vocabulary {
+    words : Arc<WordsInFile>,
+    keywords : ListedKeywords,
+    stopwords : StopwordPolicy,
+    spelling : SpellingCorrector,
+}
+
+impl Vocabulary {
+    /// Load the listed keywords and stopwords, normalized as in the words, and build the spelling corrector.
+    fn load(words:Arc<WordsInFile>) -> anyhow::Result<Self> {
+        let keywords = ListedKeywords::load_with_normalization(ListedKeywords::STD_LOCATION,words.normalization())?;
+        let stopwords = if std::path::Path::new(StopwordPolicy::STD_LOCATION).exists() { StopwordPolicy::load_word_list(StopwordPolicy::STD_LOCATION,&words)? } else { StopwordPolicy::default() };
+        let spelling = SpellingCorrector::new(&words,&keywords,Default::default());
+        Ok(Vocabulary{words,keywords,stopwords,spelling})
+    }
+
+    /// The tokenizer for questions added to the database.
+    fn tokenizer<'a>(&'a self,other_languages:&'a LanguageVocabularies) -> MultilingualTokenizer<'a> {
+        MultilingualTokenizer::new(&self.words,other_languages,&self.keywords)
+    }
+
+    /// The tokenizer for questions being searched for, which also corrects spelling.
+    fn query_tokenizer<'a>(&'a self,other_languages:&'a LanguageVocabularies) -> SpellingCorrection<'a,MultilingualTokenizer<'a>> {
+        SpellingCorrection::new(self.tokenizer(other_languages),&self.spelling)
+    }
+}
+
+/// The vocabulary currently in use. Handlers take a copy of the Arc, so a reload does not change it underneath them.
+struct CurrentVocabulary(RwLock<Arc<Vocabulary>>);
+
+impl CurrentVocabulary {
+    fn get(&self) -> Arc<Vocabulary> { self.0.read().unwrap().clone() }
+    fn replace(&self,vocabulary:Vocabulary) { *self.0.write().unwrap() = Arc::new(vocabulary); }
+}
+
 
 
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
-    let words = WordsInFile::read_word_file(WORD_MMAP_FILE)?;
-    let keywords = ListedKeywords::load(ListedKeywords::STD_LOCATION)?;
-    let filename : &str = FlatfileDatabaseBackend::<QuestionID>::STD_FILE_NAME;
-    let questions : FlatfileDatabaseBackend<QuestionID> = FlatfileDatabaseBackend::<QuestionID>::new(filename,&words,&keywords)?;
+    let synonym_overlay = if std::path::Path::new(SynonymOverlay::STD_LOCATION).exists() { Some(SynonymOverlay::load(SynonymOverlay::STD_LOCATION)?) } else { None };
+    let lemmas = if std::path::Path::new(Lemmas::STD_LOCATION).exists() { Some(Lemmas::load(Lemmas::STD_LOCATION)?) } else { None };
+    let (words,unknown_overlay_words) = ReloadableWordsInFile::load_with_overlays(WORD_MMAP_FILE,synonym_overlay,lemmas)?;
+    warn_unknown_overlay_words(&unknown_overlay_words);
+    if words.current().format_version()==0 { println!("Warning : {} is in the old unversioned word file format, with no checksum. Consider recreating it.",WORD_MMAP_FILE); }
+    let vocabulary = Vocabulary::load(words.current())?;
+    let other_languages = LanguageVocabularies::load_available()?;
+    other_languages.check_index_ranges(&vocabulary.words,LanguageVocabularies::INDEX_RANGES_STD_LOCATION)?;
+    let questions : FlatfileDatabaseBackend<QuestionID> = FlatfileDatabaseBackend::<QuestionID>::new(QUESTION_DATABASE_FILE,&vocabulary.tokenizer(&other_languages),&vocabulary.stopwords)?;
     let questions = web::Data::new(Mutex::new(questions));
+    let vocabulary = web::Data::new(CurrentVocabulary(RwLock::new(Arc::new(vocabulary))));
     let words = web::Data::new(words);
-    let keywords = web::Data::new(keywords);
-    //reload_from_textfile(questions.lock().await.deref_mut(),&words,&keywords)?;
+    let other_languages = web::Data::new(other_languages);
+    actix_web::rt::spawn(watch_vocabulary(words,vocabulary.clone(),other_languages.clone(),questions.clone()));
+    //reload_from_textfile(questions.lock().await.deref_mut(),&vocabulary.tokenizer(&other_languages),&vocabulary.stopwords)?;
     println!("Running demo webserver on http://localhost:8091");
     HttpServer::new(move|| {
         actix_web::App::new()
             .app_data(questions.clone())
-            .app_data(words.clone())
-            .app_data(keywords.clone())
+            .app_data(vocabulary.clone())
+            .app_data(other_languages.clone())
             .wrap(middleware::Compress::default())
             .service(get_question)
             .service(get_all_questions)
             .service(get_similar)
+            .service(get_similar_with_matches)
+            .service(explain)
             .service(submit_question)
             .service(actix_files::Files::new("/", find_web_resources()).use_last_modified(true).use_etag(true).index_file("index.html"))
     })
@@ -107,16 +182,59 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn warn_unknown_overlay_words(unknown:&[String]) {
+    for word in unknown { println!("Warning : synonym overlay word {} is not in {}",word,WORD_MMAP_FILE); }
+}
+
+const QUESTION_DATABASE_FILE : &str = FlatfileDatabaseBackend::<QuestionID>::STD_FILE_NAME;
+
+/// Periodically check whether the general vocabulary file has been replaced, and if so swap in the new one.
+/// Word indices change with the vocabulary, so the listed keywords, stopwords, spelling corrector and question database
+/// are all rebuilt with the new one. This is done on a blocking thread, and only swapped in at the end, while holding
+/// the question database lock, so queries (which hold that lock while they search) see a consistent database and vocabulary.
+/// Questions submitted during the rebuild are in the file but may not be in the rebuilt database, so they are read
+/// again (with the new vocabulary) before swapping.
+async fn watch_vocabulary(words:web::Data<ReloadableWordsInFile>,vocabulary:web::Data<CurrentVocabulary>,other_languages:web::Data<LanguageVocabularies>,question_db:web::Data<Mutex<QuestionDatabase>>) {
+    loop {
+        actix_web::rt::time::sleep(Duration::from_secs(10)).await;
+        let rebuild = {
+            let (words,other_languages) = (words.clone(),other_languages.clone());
+            web::block(move ||->anyhow::Result<Option<(Arc<WordsInFile>,Vocabulary,QuestionDatabase)>> {
+                let new_words = match words.check_for_new_version()? { Some((new_words,unknown_overlay_words)) => { warn_unknown_overlay_words(&unknown_overlay_words); Arc::new(new_words) }, None => return Ok(None) };
+                let new_vocabulary = Vocabulary::load(new_words.clone())?;
+                let rebuilt = QuestionDatabase::new(QUESTION_DATABASE_FILE,&new_vocabulary.tokenizer(&other_languages),&new_vocabulary.stopwords)?;
+                Ok(Some((new_words,new_vocabulary,rebuilt)))
+            }).await
+        };
+        match rebuild {
+            Ok(Ok(Some((new_words,new_vocabulary,mut rebuilt)))) => {
+                let mut question_db = question_db.lock().await;
+                if let Err(e) = rebuilt.read_new_questions(&new_vocabulary.tokenizer(&other_languages),&new_vocabulary.stopwords) {
+                    println!("Could not load new version of {} : {}",words.path().display(),e);
+                    continue;
+                }
+                *question_db = rebuilt;
+                vocabulary.replace(new_vocabulary);
+                words.replace(new_words);
+                println!("Loaded new version of {}",words.path().display());
+            }
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => println!("Could not load new version of {} : {}",words.path().display(),e),
+            Err(e) => println!("Could not load new version of {} : {}",words.path().display(),e),
+        }
+    }
+}
+
 /// Load the database from a file containing a list of questions one per line.
-pub fn reload_from_textfile(questions : &mut FlatfileDatabaseBackend<QuestionID>,words:&WordsInFile,keywords:&ListedKeywords) -> anyhow::Result<()> {
+pub fn reload_from_textfile<T:Tokenizer>(questions : &mut FlatfileDatabaseBackend<QuestionID>,tokenizer:&T,stopwords:&StopwordPolicy) -> anyhow::Result<()> {
     use std::io::BufRead;
     questions.clear_all_reinitialize()?;
     let mut count = 0;
     if let Ok(file) = std::fs::File::open("SampleTextDatabase.txt") {
         for line in std::io::BufReader::new(file).lines() {
-            add_question(questions,&line?,count,words,keywords)?;
+            add_question(questions,&line?,count,tokenizer,stopwords)?;
             count+=1;
         }
     }
     Ok(())
-}
\ No newline at end of file
+}